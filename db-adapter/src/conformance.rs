@@ -0,0 +1,500 @@
+//! Reusable checks for `DbAdapter` implementations.
+//!
+//! Every adapter is expected to behave like this:
+//! - `insert` of an unknown `(id, start_time)` adds a new matchup;
+//! - `insert` of a known `(id, start_time)` replaces the stored matchup;
+//! - `select_by_date_range` returns matchups with `start_time >= start_date`
+//!   and `end_time <= end_date`, both bounds inclusive;
+//! - results are ordered by `id` and then `start_time`;
+//! - `stream_by_date_range` yields the same matchups as `select_by_date_range`;
+//! - a matchup is read back exactly as it was inserted;
+//! - `query` gives the same result as `MatchupQuery::apply`;
+//! - `select_events` gives the same result as `EventQuery::apply`, for
+//!   adapters storing events, checked apart by `check_events`;
+//! - worlds and objectives replace the stored ones with the same id, and are
+//!   read back by id, for adapters storing them, checked apart by
+//!   `check_reference`.
+//!
+//! The checks write matchups dated from January 2000, worlds and objectives
+//! with made up ids, and never remove them.
+//! Most use ids with a prefix of their own, but queries filter on real match
+//! ids, so `check_query` stores matchups with ids like `1-1`. Run the suite
+//! against a database kept for it, never one holding real data: the ignored
+//! tests of the backends read its name from `POSTGRES_CONFORMANCE_DATABASE`,
+//! `MONGO_CONFORMANCE_DATABASE` or `DYNAMO_CONFORMANCE_TABLE`.
+//!
+//! Built for the tests of this crate, and for other crates with the
+//! `conformance` feature.
+
+use std::error::Error;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use futures::TryStreamExt;
+use gw2_api_models::models::{
+    matchup_event::{EventKind, MatchupEvent},
+    matchup_overview::{mock, MatchupOverview},
+    objective::Objective,
+    world::World,
+};
+
+use crate::{
+    db_adapter::{DbAdapter, EventAdapter, ReferenceAdapter},
+    query::{EventQuery, MatchupQuery, Region, SortDirection, SortField},
+};
+
+const DETAILED_MATCHUP: &str = r#"{
+    "id": "conformance-adapter-round-trip",
+    "start_time": "2000-01-07T18:00:00Z",
+    "end_time": "2000-01-14T18:00:00Z",
+    "scores": { "red": 151234, "blue": 98765, "green": 120001 },
+    "worlds": { "red": 1008, "blue": 1017, "green": 1003 },
+    "all_worlds": { "red": [1008, 1012], "blue": [1017], "green": [1003, 1020, 1023] },
+    "deaths": { "red": 4321, "blue": 3210, "green": 2109 },
+    "kills": { "red": 3000, "blue": 4000, "green": 2500 },
+    "victory_points": { "red": 145, "blue": 120, "green": 98 },
+    "skirmishes": [
+        {
+            "id": 1,
+            "scores": { "red": 512, "blue": 341, "green": 290 },
+            "map_scores": [
+                { "type": "Center", "scores": { "red": 200, "blue": 100, "green": 95 } },
+                { "type": "RedHome", "scores": { "red": 312, "blue": 241, "green": 195 } }
+            ]
+        }
+    ],
+    "maps": [
+        {
+            "id": 38,
+            "type": "Center",
+            "scores": { "red": 50000, "blue": 40000, "green": 30000 },
+            "bonuses": [ { "type": "Bloodlust", "owner": "Red" } ],
+            "objectives": [
+                {
+                    "id": "38-6",
+                    "type": "Keep",
+                    "owner": "Red",
+                    "last_flipped": "2000-01-08T10:12:31Z",
+                    "claimed_by": "C9D5A7E0-1111-2222-3333-444455556666",
+                    "claimed_at": "2000-01-08T10:13:02Z",
+                    "points_tick": 8,
+                    "points_capture": 25,
+                    "guild_upgrades": [365, 583, 178],
+                    "yaks_delivered": 140
+                },
+                {
+                    "id": "38-15",
+                    "type": "Camp",
+                    "owner": "Neutral",
+                    "last_flipped": "2000-01-07T18:00:00Z",
+                    "claimed_by": null,
+                    "claimed_at": null,
+                    "points_tick": 2,
+                    "points_capture": 2,
+                    "guild_upgrades": null,
+                    "yaks_delivered": null
+                }
+            ],
+            "deaths": { "red": 1000, "blue": 900, "green": 800 },
+            "kills": { "red": 700, "blue": 600, "green": 500 }
+        }
+    ]
+}"#;
+
+fn base_time() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2000, 1, 7, 18, 0, 0).unwrap()
+}
+
+fn week() -> Duration {
+    Duration::days(7)
+}
+
+fn only_prefixed(matchups: Vec<MatchupOverview>, prefix: &str) -> Vec<MatchupOverview> {
+    matchups
+        .into_iter()
+        .filter(|matchup| matchup.id().starts_with(prefix))
+        .collect()
+}
+
+/// A matchup with every nested structure filled in, both with and without the
+/// optional objective fields.
+pub fn detailed_matchup() -> MatchupOverview {
+    serde_json::from_str(DETAILED_MATCHUP).expect("Fixture is a valid MatchupOverview")
+}
+
+pub async fn check_all<A: DbAdapter>(adapter: &A) -> Result<(), Box<dyn Error>> {
+    check_insert(adapter).await?;
+    check_update_on_conflict(adapter).await?;
+    check_range_boundaries(adapter).await?;
+    check_ordering(adapter).await?;
+    check_stream(adapter).await?;
+    check_round_trip(adapter).await?;
+    check_query(adapter).await?;
+    Ok(())
+}
+
+pub async fn check_insert<A: DbAdapter>(adapter: &A) -> Result<(), Box<dyn Error>> {
+    let prefix = "conformance-adapter-insert-";
+    let start = base_time();
+    let end = start + week();
+    let first = mock::get_mock("conformance-adapter-insert-1", start, end);
+    let second = mock::get_mock("conformance-adapter-insert-2", start, end);
+
+    adapter.insert(&first).await?;
+    adapter.insert(&second).await?;
+    let result = only_prefixed(adapter.select_by_date_range(&start, &end).await?, prefix);
+
+    assert_eq!(
+        result,
+        vec![first, second],
+        "inserted matchups must be selected"
+    );
+    Ok(())
+}
+
+pub async fn check_update_on_conflict<A: DbAdapter>(adapter: &A) -> Result<(), Box<dyn Error>> {
+    let prefix = "conformance-adapter-update-";
+    let start = base_time();
+    let end = start + week();
+    let mut original = mock::get_mock("conformance-adapter-update-1", start, end);
+    mock::set_scores(&mut original, 1, 2, 3);
+    let mut updated = original.clone();
+    mock::set_scores(&mut updated, 4, 5, 6);
+
+    adapter.insert(&original).await?;
+    adapter.insert(&updated).await?;
+    let result = only_prefixed(adapter.select_by_date_range(&start, &end).await?, prefix);
+
+    assert_eq!(
+        result,
+        vec![updated],
+        "inserting a known (id, start_time) must replace the stored matchup"
+    );
+    Ok(())
+}
+
+pub async fn check_range_boundaries<A: DbAdapter>(adapter: &A) -> Result<(), Box<dyn Error>> {
+    let prefix = "conformance-adapter-range-";
+    let start = base_time();
+    let end = start + week();
+    let one_second = Duration::seconds(1);
+    let on_bounds = mock::get_mock("conformance-adapter-range-on-bounds", start, end);
+    let inside = mock::get_mock(
+        "conformance-adapter-range-inside",
+        start + one_second,
+        end - one_second,
+    );
+    let starts_before = mock::get_mock(
+        "conformance-adapter-range-starts-before",
+        start - one_second,
+        end,
+    );
+    let ends_after = mock::get_mock(
+        "conformance-adapter-range-ends-after",
+        start,
+        end + one_second,
+    );
+
+    for matchup in [&on_bounds, &inside, &starts_before, &ends_after] {
+        adapter.insert(matchup).await?;
+    }
+    let result = only_prefixed(adapter.select_by_date_range(&start, &end).await?, prefix);
+
+    assert_eq!(
+        result,
+        vec![inside, on_bounds],
+        "only matchups fully contained in the range, bounds included, must be selected"
+    );
+    Ok(())
+}
+
+pub async fn check_ordering<A: DbAdapter>(adapter: &A) -> Result<(), Box<dyn Error>> {
+    let prefix = "conformance-adapter-order-";
+    let start = base_time();
+    let next_start = start + week();
+    let end = next_start + week();
+    let a_first = mock::get_mock("conformance-adapter-order-a", start, next_start);
+    let a_second = mock::get_mock("conformance-adapter-order-a", next_start, end);
+    let b_first = mock::get_mock("conformance-adapter-order-b", start, next_start);
+
+    for matchup in [&b_first, &a_second, &a_first] {
+        adapter.insert(matchup).await?;
+    }
+    let result = only_prefixed(adapter.select_by_date_range(&start, &end).await?, prefix);
+
+    assert_eq!(
+        result,
+        vec![a_first, a_second, b_first],
+        "results must be ordered by id and then start_time"
+    );
+    Ok(())
+}
+
+pub async fn check_stream<A: DbAdapter>(adapter: &A) -> Result<(), Box<dyn Error>> {
+    let prefix = "conformance-adapter-stream-";
+    let start = base_time();
+    let next_start = start + week();
+    let end = next_start + week();
+    let b_first = mock::get_mock("conformance-adapter-stream-b", start, next_start);
+    let a_second = mock::get_mock("conformance-adapter-stream-a", next_start, end);
+    let a_first = mock::get_mock("conformance-adapter-stream-a", start, next_start);
+    let mut b_upserted = b_first.clone();
+    mock::set_scores(&mut b_upserted, 7, 8, 9);
+
+    for matchup in [&b_first, &a_second, &a_first, &b_upserted] {
+        adapter.insert(matchup).await?;
+    }
+    let streamed: Vec<MatchupOverview> = adapter
+        .stream_by_date_range(&start, &end)
+        .await?
+        .try_collect()
+        .await?;
+
+    assert_eq!(
+        only_prefixed(streamed, prefix),
+        vec![a_first, a_second, b_upserted],
+        "streamed matchups must be the ones selected, in the same order"
+    );
+    Ok(())
+}
+
+pub async fn check_round_trip<A: DbAdapter>(adapter: &A) -> Result<(), Box<dyn Error>> {
+    let prefix = "conformance-adapter-round-trip";
+    let matchup = detailed_matchup();
+
+    adapter.insert(&matchup).await?;
+    let result = only_prefixed(
+        adapter
+            .select_by_date_range(matchup.start_time(), matchup.end_time())
+            .await?,
+        prefix,
+    );
+
+    assert_eq!(result, vec![matchup], "matchup must be read back unchanged");
+    Ok(())
+}
+
+pub async fn check_query<A: DbAdapter>(adapter: &A) -> Result<(), Box<dyn Error>> {
+    let start = base_time() + week() * 8;
+    let next_start = start + week();
+    let end = next_start + week();
+    let mut na_1_first = mock::get_mock("1-1", start, next_start);
+    mock::set_all_worlds(&mut na_1_first, vec![1001, 1010], vec![1002], vec![1003]);
+    let na_1_second = mock::get_mock("1-1", next_start, end);
+    let na_2 = mock::get_mock("1-2", start, next_start);
+    let eu_1 = mock::get_mock("2-1", start, next_start);
+    for matchup in [&eu_1, &na_1_second, &na_2, &na_1_first] {
+        adapter.insert(matchup).await?;
+    }
+    let in_range = || MatchupQuery::new().contained_in(start, end);
+
+    assert_eq!(
+        adapter.query(&in_range().match_id("1-1")).await?,
+        vec![na_1_first.clone(), na_1_second.clone()],
+        "query must filter by match id"
+    );
+    assert_eq!(
+        adapter.query(&in_range().world_id(1010)).await?,
+        vec![na_1_first.clone()],
+        "query must filter by linked world"
+    );
+    assert_eq!(
+        adapter.query(&in_range().region(Region::Europe)).await?,
+        vec![eu_1.clone()],
+        "query must filter by region"
+    );
+    assert_eq!(
+        adapter
+            .query(&in_range().region(Region::NorthAmerica).tier(2))
+            .await?,
+        vec![na_2.clone()],
+        "query must filter by tier"
+    );
+
+    let first_day = start + Duration::days(1);
+    let second_day = first_day + Duration::days(1);
+    assert_eq!(
+        adapter
+            .query(&MatchupQuery::new().overlapping(first_day, second_day))
+            .await?,
+        vec![na_1_first.clone(), na_2.clone(), eu_1.clone()],
+        "overlapping must include matchups partially in the range"
+    );
+    assert_eq!(
+        adapter
+            .query(&MatchupQuery::new().contained_in(first_day, second_day))
+            .await?,
+        vec![],
+        "contained must exclude matchups partially in the range"
+    );
+
+    assert_eq!(
+        adapter.query(&in_range().latest_per_match()).await?,
+        vec![na_1_second.clone(), na_2.clone(), eu_1.clone()],
+        "latest per match must keep the last start of each match"
+    );
+    assert_eq!(
+        adapter
+            .query(&in_range().sort(SortField::StartTime, SortDirection::Descending))
+            .await?,
+        vec![
+            na_1_second.clone(),
+            na_1_first.clone(),
+            na_2.clone(),
+            eu_1.clone()
+        ],
+        "ties on the sort field must be ordered by id and start time"
+    );
+    assert_eq!(
+        adapter
+            .query(
+                &in_range()
+                    .sort(SortField::MatchId, SortDirection::Descending)
+                    .page(1, 2)
+            )
+            .await?,
+        vec![na_2, na_1_first],
+        "pages must be cut after sorting"
+    );
+    Ok(())
+}
+
+pub async fn check_events<A: EventAdapter>(adapter: &A) -> Result<(), Box<dyn Error>> {
+    // Events are appended, so each run reads only its own.
+    let prefix = format!("conformance-events-{}-", Utc::now().timestamp_micros());
+    let (a, b) = (format!("{}a", prefix), format!("{}b", prefix));
+    let start = base_time();
+    let event = |match_id: &str, minutes: i64, kind: EventKind| MatchupEvent {
+        match_id: match_id.to_string(),
+        start_time: start,
+        detected_at: start + Duration::minutes(minutes),
+        kind,
+    };
+    let new_matchup = event(
+        &b,
+        0,
+        EventKind::NewMatchup {
+            previous_start_time: Some(start - week()),
+        },
+    );
+    let tier_change = event(
+        &b,
+        0,
+        EventKind::TierChange {
+            world: 1001,
+            previous_match_id: a.clone(),
+        },
+    );
+    let relink = event(
+        &a,
+        5,
+        EventKind::Relink {
+            world: 1002,
+            previous_worlds: vec![1002, 1010],
+            worlds: vec![1002, 1012],
+        },
+    );
+
+    adapter
+        .insert_events(&[new_matchup.clone(), tier_change.clone()])
+        .await?;
+    adapter.insert_events(std::slice::from_ref(&relink)).await?;
+    let in_range = || EventQuery::new().detected_between(start, start + Duration::minutes(5));
+    let only_prefixed = |events: Vec<MatchupEvent>| -> Vec<MatchupEvent> {
+        events
+            .into_iter()
+            .filter(|event| event.match_id.starts_with(&prefix))
+            .collect()
+    };
+
+    assert_eq!(
+        only_prefixed(adapter.select_events(&in_range()).await?),
+        vec![new_matchup.clone(), tier_change, relink.clone()],
+        "events must be read back by detection time, in the order they were saved"
+    );
+    assert_eq!(
+        adapter
+            .select_events(&in_range().match_id(&b).limit(1))
+            .await?,
+        vec![new_matchup],
+        "events must be filtered by match id and limited"
+    );
+    assert_eq!(
+        only_prefixed(
+            adapter
+                .select_events(
+                    &EventQuery::new()
+                        .detected_between(start + Duration::seconds(1), start + week())
+                )
+                .await?
+        ),
+        vec![relink],
+        "the detection range must be applied"
+    );
+    Ok(())
+}
+
+pub async fn check_reference<A: ReferenceAdapter>(adapter: &A) -> Result<(), Box<dyn Error>> {
+    let world = |id: u64, population: &str| -> Result<World, serde_json::Error> {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": format!("Conformance World {}", id),
+            "population": population,
+        }))
+    };
+    let objective = |id: &str, name: &str| -> Result<Objective, serde_json::Error> {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": name,
+            "sector_id": 1099,
+            "type": "Camp",
+            "map_type": "Center",
+            "map_id": 38,
+            "upgrade_id": 8,
+            "coord": [-9472.0, 13000.5, -1200.25],
+            "label_coord": [-9470.0, 12990.0],
+            "marker": "https://render.guildwars2.com/file/camp.png",
+            "chat_link": "[&DAYAAAAmAAAA]",
+        }))
+    };
+    let (first, second) = (world(9902, "High")?, world(9901, "Medium")?);
+    adapter
+        .upsert_worlds(&[first.clone(), second.clone()])
+        .await?;
+    let first = world(9902, "Full")?;
+    adapter.upsert_worlds(std::slice::from_ref(&first)).await?;
+    let worlds: Vec<World> = adapter
+        .select_worlds()
+        .await?
+        .into_iter()
+        .filter(|world| (9901..=9902).contains(world.id()))
+        .collect();
+    assert_eq!(
+        worlds,
+        vec![second, first],
+        "worlds must be replaced by id and read back by id"
+    );
+
+    let (camp, tower) = (
+        objective("conformance-2", "Camp")?,
+        objective("conformance-1", "Tower")?,
+    );
+    adapter
+        .upsert_objectives(&[camp.clone(), tower.clone()])
+        .await?;
+    let camp = objective("conformance-2", "Renamed camp")?;
+    adapter
+        .upsert_objectives(std::slice::from_ref(&camp))
+        .await?;
+    let objectives: Vec<Objective> = adapter
+        .select_objectives()
+        .await?
+        .into_iter()
+        .filter(|objective| objective.id().starts_with("conformance-"))
+        .collect();
+    assert_eq!(
+        objectives,
+        vec![tower, camp],
+        "objectives must be replaced by id and read back exactly, by id"
+    );
+    Ok(())
+}
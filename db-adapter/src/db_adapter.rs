@@ -1,14 +1,52 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use gw2_api_models::models::matchup_overview::MatchupOverview;
-use std::error::Error;
+use futures::stream::BoxStream;
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
+
+use crate::{
+    error::PersistenceError,
+    query::{EventQuery, MatchupQuery},
+};
+
+/// Matchups read one at a time, in the same order as `select_by_date_range`.
+pub type MatchupStream<'a> = BoxStream<'a, Result<MatchupOverview, PersistenceError>>;
 
 #[async_trait]
 pub trait DbAdapter {
-    async fn insert(&self, obj: &MatchupOverview) -> Result<(), Box<dyn Error>>;
+    async fn insert(&self, obj: &MatchupOverview) -> Result<(), PersistenceError>;
     async fn select_by_date_range(
         &self,
         start_date: &DateTime<Utc>,
         end_date: &DateTime<Utc>,
-    ) -> Result<Vec<MatchupOverview>, Box<dyn Error>>;
+    ) -> Result<Vec<MatchupOverview>, PersistenceError>;
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError>;
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError>;
+}
+
+/// Backends that also keep the events detected between captures.
+#[async_trait]
+pub trait EventAdapter {
+    async fn insert_events(&self, events: &[MatchupEvent]) -> Result<(), PersistenceError>;
+    async fn select_events(
+        &self,
+        query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError>;
+}
+
+/// Backends that also keep the worlds and objectives, which rarely change.
+/// Saving one replaces the stored one with the same id, and they are read
+/// back ordered by id.
+#[async_trait]
+pub trait ReferenceAdapter {
+    async fn upsert_worlds(&self, worlds: &[World]) -> Result<(), PersistenceError>;
+    async fn select_worlds(&self) -> Result<Vec<World>, PersistenceError>;
+    async fn upsert_objectives(&self, objectives: &[Objective]) -> Result<(), PersistenceError>;
+    async fn select_objectives(&self) -> Result<Vec<Objective>, PersistenceError>;
 }
@@ -1,50 +1,74 @@
-use std::{error::Error, time::Duration};
+use std::{collections::HashMap, error::Error, fmt::Debug, time::Duration};
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use dynamodb::types::AttributeValue;
+use dynamodb::{
+    error::{ProvideErrorMetadata, SdkError},
+    types::AttributeValue,
+};
+use futures::{stream, StreamExt};
 use gw2_api_models::models::matchup_overview::MatchupOverview;
 
 use aws_config;
 use aws_sdk_dynamodb as dynamodb;
 use serde_json;
 
-use crate::db_adapter;
+use crate::{
+    db_adapter::{self, MatchupStream},
+    error::PersistenceError,
+    query::MatchupQuery,
+};
 
-#[derive(Debug, Clone)]
-pub struct DynamoAdapterConfig {
-    aws_config: aws_config::SdkConfig,
-}
+/// Table the matchups are kept in, unless told otherwise.
+pub const TABLE_NAME: &str = "gw2-wvw-scrapper";
 
 #[derive(Debug, Clone)]
 pub struct DynamoAdapter {
     time_to_sleep: Duration,
-    config: DynamoAdapterConfig,
+    /// Shared by every connection, so they reuse its HTTP connections.
+    client: dynamodb::Client,
+    table: String,
 }
 
 impl DynamoAdapter {
     pub async fn new() -> Self {
+        Self::with_table(TABLE_NAME).await
+    }
+
+    pub async fn with_table(table: &str) -> Self {
         let aws_config = aws_config::load_from_env().await;
 
-        let config = DynamoAdapterConfig { aws_config };
+        let client = dynamodb::Client::new(&aws_config);
         Self {
             time_to_sleep: Duration::from_secs(10),
-            config,
+            client,
+            table: table.to_string(),
         }
     }
 
-    pub async fn get_connection(&self) -> Result<DynamoClientAdapter, aws_sdk_dynamodb::Error> {
-        let client = dynamodb::Client::new(&self.config.aws_config);
-        Ok(DynamoClientAdapter {
-            time_to_sleep: self.time_to_sleep,
-            client,
-        })
+    pub async fn get_connection(&self) -> Result<DynamoClientAdapter, PersistenceError> {
+        Ok(
+            DynamoClientAdapter::new(self.time_to_sleep, self.client.clone())
+                .with_table(&self.table),
+        )
+    }
+
+    /// Round trip to the table, to tell whether it can be used.
+    pub async fn ping(&self) -> Result<(), PersistenceError> {
+        self.client
+            .describe_table()
+            .table_name(&self.table)
+            .send()
+            .await
+            .map_err(dynamo_error)?;
+        Ok(())
     }
 }
 
 pub struct DynamoClientAdapter {
     time_to_sleep: Duration,
     client: dynamodb::Client,
+    table: String,
 }
 
 impl DynamoClientAdapter {
@@ -52,18 +76,23 @@ impl DynamoClientAdapter {
         Self {
             time_to_sleep,
             client,
+            table: TABLE_NAME.to_string(),
         }
     }
+
+    pub fn with_table(mut self, table: &str) -> Self {
+        self.table = table.to_string();
+        self
+    }
 }
 
 #[async_trait]
 impl db_adapter::DbAdapter for DynamoClientAdapter {
-    async fn insert(&self, data: &MatchupOverview) -> Result<(), Box<dyn Error>> {
+    async fn insert(&self, data: &MatchupOverview) -> Result<(), PersistenceError> {
         let matchup_key = format!("{} {}", data.id(), data.start_time());
         let start_time = data.start_time().to_rfc3339();
         let end_time = data.end_time().to_rfc3339();
-        let content =
-            serde_json::to_string(data).expect("Can serialize MatchupOverview data to string");
+        let content = serde_json::to_string(data)?;
 
         let matchup_key_value = AttributeValue::S(matchup_key);
         let start_time_value = AttributeValue::S(start_time);
@@ -73,13 +102,14 @@ impl db_adapter::DbAdapter for DynamoClientAdapter {
         let _ = self
             .client
             .put_item()
-            .table_name("gw2-wvw-scrapper")
+            .table_name(&self.table)
             .item("matchup_key", matchup_key_value)
             .item("matchup_start_date", start_time_value)
             .item("matchup_end_date", end_time_value)
             .item("content", content_value)
             .send()
-            .await?;
+            .await
+            .map_err(dynamo_error)?;
         // Sleep so the cota doesn't exceed
         tokio::time::sleep(self.time_to_sleep).await;
         Ok(())
@@ -89,49 +119,98 @@ impl db_adapter::DbAdapter for DynamoClientAdapter {
         &self,
         start_date: &DateTime<Utc>,
         end_date: &DateTime<Utc>,
-    ) -> Result<Vec<MatchupOverview>, Box<dyn Error>> {
-        let start_time = start_date.to_rfc3339();
-        let end_time = end_date.to_rfc3339();
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        self.query(&MatchupQuery::new().contained_in(*start_date, *end_date))
+            .await
+    }
 
-        let start_time_value = AttributeValue::S(start_time);
-        let end_time_value = AttributeValue::S(end_time);
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        // Scans come back unordered, so the whole range is read before streaming.
+        let matchups = self.select_by_date_range(start_date, end_date).await?;
+        Ok(stream::iter(matchups.into_iter().map(Ok)).boxed())
+    }
 
-        let query = self.client.query();
-        let table = query.table_name("gw2-wvw-scrapper");
-        let filter = table
-            .filter_expression("matchup_start_date >= :date_1 AND matchup_end_date <= :date_2")
-            .expression_attribute_values(":date_1", start_time_value)
-            .expression_attribute_values(":date_2", end_time_value);
-
-        let result = filter.send().await;
-        dbg!(&start_date);
-        match result {
-            Ok(data) => {
-                dbg!(data);
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        // The table is only keyed by matchup, so the query runs on the client
+        // over a full scan.
+        let mut matchups: Vec<MatchupOverview> = vec![];
+        let mut exclusive_start_key: Option<HashMap<String, AttributeValue>> = None;
+        loop {
+            let page = self
+                .client
+                .scan()
+                .table_name(&self.table)
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await
+                .map_err(dynamo_error)?;
+            for item in page.items().unwrap_or_default() {
+                let content = item
+                    .get("content")
+                    .and_then(|content| content.as_s().ok())
+                    .ok_or_else(|| {
+                        PersistenceError::Serialization("Dynamo item has no content".to_string())
+                    })?;
+                matchups.push(serde_json::from_str(content)?);
             }
-            Err(err) => {
-                dbg!(err);
+            exclusive_start_key = page.last_evaluated_key().cloned();
+            if exclusive_start_key.is_none() {
+                break;
             }
         }
+        Ok(query.apply(matchups))
+    }
+}
 
-        // if let Some(items) = result?.items {
-        //     let matchups: Vec<MatchupOverview> = items
-        //         .iter()
-        //         .map(|data| {
-        //             let content_value = data.get("content").expect("Could not retrieve content");
-        //             let content = content_value
-        //                 .as_s()
-        //                 .expect("Could not convert content value to String");
-        //             let matchup_data: MatchupOverview = serde_json::from_str(content)
-        //                 .expect("Could not convert content String to Struct MatchupOverview");
-        //             matchup_data
-        //         })
-        //         .collect();
-        //     return Ok(matchups);
-        // }
-        return Ok(vec![]);
+/// Classifies SDK failures by how the request failed, and then by the error
+/// code DynamoDB answered with.
+fn dynamo_error<E, R>(err: SdkError<E, R>) -> PersistenceError
+where
+    E: ProvideErrorMetadata + Error + 'static,
+    R: Debug,
+{
+    let message = err.to_string();
+    match &err {
+        SdkError::TimeoutError(_) => PersistenceError::Timeout(message),
+        SdkError::DispatchFailure(_) => PersistenceError::Connection(message),
+        _ => match err.code() {
+            Some(
+                "ProvisionedThroughputExceededException"
+                | "ThrottlingException"
+                | "RequestLimitExceeded",
+            ) => PersistenceError::Throttled(message),
+            Some("ConditionalCheckFailedException" | "TransactionConflictException") => {
+                PersistenceError::Conflict(message)
+            }
+            Some("ResourceNotFoundException") => PersistenceError::NotFound(message),
+            Some("UnrecognizedClientException" | "AccessDeniedException") => {
+                PersistenceError::Connection(message)
+            }
+            _ => PersistenceError::Backend(message),
+        },
     }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::{env, error::Error, time::Duration};
+
+    use aws_sdk_dynamodb as dynamodb;
+
+    use crate::{conformance, dynamo_adapter::DynamoClientAdapter};
+
+    #[tokio::test]
+    #[ignore = "needs AWS credentials and a DynamoDB table keyed by matchup_key, named by DYNAMO_CONFORMANCE_TABLE"]
+    async fn passes_conformance_suite() -> Result<(), Box<dyn Error>> {
+        let table = env::var("DYNAMO_CONFORMANCE_TABLE")
+            .unwrap_or_else(|_| "gw2-wvw-scrapper-conformance".to_string());
+        let client = dynamodb::Client::new(&aws_config::load_from_env().await);
+        // No pause between inserts, the suite is too small to hit the quota.
+        let adapter = DynamoClientAdapter::new(Duration::ZERO, client).with_table(&table);
+        conformance::check_all(&adapter).await
+    }
+}
@@ -0,0 +1,215 @@
+use std::{fmt::Display, io};
+
+use deadpool_postgres::PoolError;
+use mongodb::error::{ErrorKind, WriteFailure};
+use thiserror::Error;
+use tokio_postgres::error::SqlState;
+
+/// What went wrong while reading or writing matchups, whatever the backend.
+///
+/// Messages are kept as text so errors can be cloned, and sent across tasks.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PersistenceError {
+    /// The backend could not be reached, or refused the credentials.
+    #[error("connection failed: {0}")]
+    Connection(String),
+    /// The write clashes with data already stored.
+    #[error("conflicting write: {0}")]
+    Conflict(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    /// A matchup could not be encoded for, or decoded from, the backend.
+    #[error("serialization failed: {0}")]
+    Serialization(String),
+    #[error("timed out: {0}")]
+    Timeout(String),
+    /// The backend asks to slow down; retrying later may succeed.
+    #[error("throttled: {0}")]
+    Throttled(String),
+    /// Any other failure reported by the backend.
+    #[error("{0}")]
+    Backend(String),
+}
+
+impl PersistenceError {
+    /// Same kind of error, with `context` in front of its message.
+    pub fn context(self, context: impl Display) -> Self {
+        let with_context = |message: String| format!("{}: {}", context, message);
+        match self {
+            PersistenceError::Connection(message) => {
+                PersistenceError::Connection(with_context(message))
+            }
+            PersistenceError::Conflict(message) => {
+                PersistenceError::Conflict(with_context(message))
+            }
+            PersistenceError::NotFound(message) => {
+                PersistenceError::NotFound(with_context(message))
+            }
+            PersistenceError::Serialization(message) => {
+                PersistenceError::Serialization(with_context(message))
+            }
+            PersistenceError::Timeout(message) => PersistenceError::Timeout(with_context(message)),
+            PersistenceError::Throttled(message) => {
+                PersistenceError::Throttled(with_context(message))
+            }
+            PersistenceError::Backend(message) => PersistenceError::Backend(with_context(message)),
+        }
+    }
+
+    /// Whether the same call may succeed later without any change.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            PersistenceError::Connection(_)
+                | PersistenceError::Timeout(_)
+                | PersistenceError::Throttled(_)
+        )
+    }
+}
+
+impl From<io::Error> for PersistenceError {
+    fn from(err: io::Error) -> Self {
+        let message = err.to_string();
+        match err.kind() {
+            io::ErrorKind::NotFound => PersistenceError::NotFound(message),
+            io::ErrorKind::AlreadyExists => PersistenceError::Conflict(message),
+            io::ErrorKind::TimedOut => PersistenceError::Timeout(message),
+            io::ErrorKind::InvalidData => PersistenceError::Serialization(message),
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::BrokenPipe => PersistenceError::Connection(message),
+            _ => PersistenceError::Backend(message),
+        }
+    }
+}
+
+impl From<serde_json::Error> for PersistenceError {
+    fn from(err: serde_json::Error) -> Self {
+        PersistenceError::Serialization(err.to_string())
+    }
+}
+
+impl From<mongodb::error::Error> for PersistenceError {
+    fn from(err: mongodb::error::Error) -> Self {
+        // https://www.mongodb.com/docs/manual/reference/error-codes/
+        const DUPLICATE_KEY: i32 = 11000;
+        const MAX_TIME_EXPIRED: i32 = 50;
+        const REQUEST_RATE_TOO_LARGE: i32 = 16500;
+
+        let message = err.to_string();
+        let code = match err.kind.as_ref() {
+            ErrorKind::Command(command) => Some(command.code),
+            ErrorKind::Write(WriteFailure::WriteError(write)) => Some(write.code),
+            _ => None,
+        };
+        match (err.kind.as_ref(), code) {
+            (_, Some(DUPLICATE_KEY)) => PersistenceError::Conflict(message),
+            (_, Some(MAX_TIME_EXPIRED)) => PersistenceError::Timeout(message),
+            (_, Some(REQUEST_RATE_TOO_LARGE)) => PersistenceError::Throttled(message),
+            (ErrorKind::BsonSerialization(_) | ErrorKind::BsonDeserialization(_), _) => {
+                PersistenceError::Serialization(message)
+            }
+            (
+                ErrorKind::Io(_)
+                | ErrorKind::DnsResolve { .. }
+                | ErrorKind::ServerSelection { .. }
+                | ErrorKind::ConnectionPoolCleared { .. }
+                | ErrorKind::Authentication { .. }
+                | ErrorKind::InvalidTlsConfig { .. },
+                _,
+            ) => PersistenceError::Connection(message),
+            _ => PersistenceError::Backend(message),
+        }
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for PersistenceError {
+    fn from(err: mongodb::bson::ser::Error) -> Self {
+        PersistenceError::Serialization(err.to_string())
+    }
+}
+
+impl From<mongodb::bson::de::Error> for PersistenceError {
+    fn from(err: mongodb::bson::de::Error) -> Self {
+        PersistenceError::Serialization(err.to_string())
+    }
+}
+
+impl From<tokio_postgres::Error> for PersistenceError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        let message = match err.as_db_error() {
+            Some(db_error) => db_error.to_string(),
+            None => err.to_string(),
+        };
+        match err.code() {
+            Some(code)
+                if *code == SqlState::UNIQUE_VIOLATION
+                    || *code == SqlState::T_R_SERIALIZATION_FAILURE =>
+            {
+                PersistenceError::Conflict(message)
+            }
+            Some(code) if *code == SqlState::QUERY_CANCELED => PersistenceError::Timeout(message),
+            Some(code) if *code == SqlState::TOO_MANY_CONNECTIONS => {
+                PersistenceError::Throttled(message)
+            }
+            Some(code) if *code == SqlState::UNDEFINED_TABLE => PersistenceError::NotFound(message),
+            Some(code)
+                if *code == SqlState::INVALID_PASSWORD
+                    || *code == SqlState::INVALID_AUTHORIZATION_SPECIFICATION =>
+            {
+                PersistenceError::Connection(message)
+            }
+            Some(_) => PersistenceError::Backend(message),
+            // Errors without a SQL state never reached the server.
+            None if err.is_closed() => PersistenceError::Connection(message),
+            None => match std::error::Error::source(&err)
+                .and_then(|source| source.downcast_ref::<serde_json::Error>())
+            {
+                Some(_) => PersistenceError::Serialization(message),
+                None => PersistenceError::Connection(message),
+            },
+        }
+    }
+}
+
+impl From<PoolError> for PersistenceError {
+    fn from(err: PoolError) -> Self {
+        match err {
+            PoolError::Backend(err) => err.into(),
+            PoolError::Timeout(_) => PersistenceError::Timeout(err.to_string()),
+            _ => PersistenceError::Connection(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::PersistenceError;
+
+    #[test]
+    fn classifies_io_errors() {
+        let missing = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        let refused = io::Error::new(io::ErrorKind::ConnectionRefused, "refused");
+        let other = io::Error::other("disk on fire");
+
+        assert!(matches!(
+            PersistenceError::from(missing),
+            PersistenceError::NotFound(_)
+        ));
+        assert!(PersistenceError::from(refused).is_transient());
+        assert_eq!(
+            PersistenceError::from(other),
+            PersistenceError::Backend("disk on fire".to_string())
+        );
+    }
+
+    #[test]
+    fn errors_can_cross_tasks() {
+        fn assert_send_sync<T: Send + Sync + 'static>() {}
+        assert_send_sync::<PersistenceError>();
+    }
+}
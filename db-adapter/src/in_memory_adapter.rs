@@ -0,0 +1,156 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
+
+use crate::{
+    db_adapter::{DbAdapter, EventAdapter, MatchupStream, ReferenceAdapter},
+    error::PersistenceError,
+    query::{EventQuery, MatchupQuery},
+};
+
+type MatchupKey = (String, DateTime<Utc>);
+
+/// Stores matchups in a map keyed by `(id, start_time)`, events in the order
+/// they were inserted, and worlds and objectives by id.
+///
+/// Clones share the same storage, like connections to the same database.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryAdapter {
+    matchups: Arc<RwLock<BTreeMap<MatchupKey, MatchupOverview>>>,
+    events: Arc<RwLock<Vec<MatchupEvent>>>,
+    worlds: Arc<RwLock<BTreeMap<u64, World>>>,
+    objectives: Arc<RwLock<BTreeMap<String, Objective>>>,
+}
+
+impl InMemoryAdapter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.matchups.read().expect("Lock is not poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait]
+impl DbAdapter for InMemoryAdapter {
+    async fn insert(&self, data: &MatchupOverview) -> Result<(), PersistenceError> {
+        self.matchups
+            .write()
+            .expect("Lock is not poisoned")
+            .insert((data.id().clone(), *data.start_time()), data.clone());
+        Ok(())
+    }
+
+    async fn select_by_date_range(
+        &self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let matchups = self.matchups.read().expect("Lock is not poisoned");
+        // The map is ordered by (id, start_time), same as the database backends.
+        let result = matchups
+            .values()
+            .filter(|matchup| matchup.start_time() >= start_date && matchup.end_time() <= end_date)
+            .cloned()
+            .collect();
+        Ok(result)
+    }
+
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        // The lock can't be held by the stream, so it iterates over a snapshot.
+        let matchups = self.select_by_date_range(start_date, end_date).await?;
+        Ok(stream::iter(matchups.into_iter().map(Ok)).boxed())
+    }
+
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let matchups = self.matchups.read().expect("Lock is not poisoned");
+        Ok(query.apply(matchups.values().cloned()))
+    }
+}
+
+#[async_trait]
+impl EventAdapter for InMemoryAdapter {
+    async fn insert_events(&self, events: &[MatchupEvent]) -> Result<(), PersistenceError> {
+        self.events
+            .write()
+            .expect("Lock is not poisoned")
+            .extend_from_slice(events);
+        Ok(())
+    }
+
+    async fn select_events(
+        &self,
+        query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+        let events = self.events.read().expect("Lock is not poisoned");
+        Ok(query.apply(events.iter().cloned()))
+    }
+}
+
+#[async_trait]
+impl ReferenceAdapter for InMemoryAdapter {
+    async fn upsert_worlds(&self, worlds: &[World]) -> Result<(), PersistenceError> {
+        self.worlds
+            .write()
+            .expect("Lock is not poisoned")
+            .extend(worlds.iter().map(|world| (*world.id(), world.clone())));
+        Ok(())
+    }
+
+    async fn select_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+        let worlds = self.worlds.read().expect("Lock is not poisoned");
+        Ok(worlds.values().cloned().collect())
+    }
+
+    async fn upsert_objectives(&self, objectives: &[Objective]) -> Result<(), PersistenceError> {
+        self.objectives
+            .write()
+            .expect("Lock is not poisoned")
+            .extend(
+                objectives
+                    .iter()
+                    .map(|objective| (objective.id().clone(), objective.clone())),
+            );
+        Ok(())
+    }
+
+    async fn select_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+        let objectives = self.objectives.read().expect("Lock is not poisoned");
+        Ok(objectives.values().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::conformance;
+
+    use super::InMemoryAdapter;
+
+    #[tokio::test]
+    async fn passes_conformance_suite() -> Result<(), Box<dyn Error>> {
+        let adapter = InMemoryAdapter::new();
+        conformance::check_all(&adapter).await?;
+        conformance::check_events(&adapter).await?;
+        conformance::check_reference(&adapter).await
+    }
+}
@@ -1,4 +1,10 @@
+#[cfg(any(test, feature = "conformance"))]
+pub mod conformance;
 pub mod db_adapter;
 pub mod dynamo_adapter;
+pub mod error;
+pub mod in_memory_adapter;
 pub mod mongo_adapter;
+pub mod pool;
 pub mod postgres_adapter;
+pub mod query;
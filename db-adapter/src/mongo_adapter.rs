@@ -1,66 +1,203 @@
+use std::time::Instant;
+
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use futures::TryStreamExt;
-use gw2_api_models::models::matchup_overview::MatchupOverview;
+use futures::{StreamExt, TryStreamExt};
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
 use mongodb::{
     bson::{self, oid::ObjectId},
-    options::{ClientOptions, FindOptions, ServerApi, ServerApiVersion, UpdateOptions},
-    Client,
+    options::{
+        ClientOptions, FindOptions, ReplaceOptions, ServerApi, ServerApiVersion, UpdateOptions,
+    },
+    Client, Collection, Cursor, IndexModel,
 };
-use std::error::Error;
+use tracing::{debug, instrument};
 
-use crate::db_adapter;
+use crate::{
+    db_adapter::{self, MatchupStream},
+    error::PersistenceError,
+    pool::PoolConfig,
+    query::{EventQuery, MatchupQuery, SortDirection, SortField, TimeRange},
+};
 
-use self::models::MatchupOverviewMongo;
+use self::models::{
+    MatchupEventMongo, MatchupOverviewMongo, ObjectiveMongo, ReferenceMongo, WorldMongo,
+};
 pub mod models;
 
-#[derive(Debug, Clone)]
-pub struct MongoAdapterConfig {
-    mongo_config: ClientOptions,
+/// Default name of both the database and the collection.
+pub const DEFAULT_NAME: &str = "gw2-wvw-scrapper";
+/// Default name of the collection holding the matchup events.
+pub const DEFAULT_EVENTS_NAME: &str = "gw2-wvw-scrapper-events";
+/// Default name of the collection holding the worlds.
+pub const DEFAULT_WORLDS_NAME: &str = "gw2-wvw-scrapper-worlds";
+/// Default name of the collection holding the objectives.
+pub const DEFAULT_OBJECTIVES_NAME: &str = "gw2-wvw-scrapper-objectives";
+
+/// Where the matchups are stored in a Mongo deployment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MongoOptions {
+    /// Any `mongodb://` or `mongodb+srv://` connection string.
+    pub uri: String,
+    pub database: String,
+    pub collection: String,
+    pub events_collection: String,
+    pub worlds_collection: String,
+    pub objectives_collection: String,
+    pub pool: PoolConfig,
 }
 
+impl MongoOptions {
+    pub fn new(uri: &str) -> Self {
+        Self {
+            uri: uri.to_string(),
+            database: DEFAULT_NAME.to_string(),
+            collection: DEFAULT_NAME.to_string(),
+            events_collection: DEFAULT_EVENTS_NAME.to_string(),
+            worlds_collection: DEFAULT_WORLDS_NAME.to_string(),
+            objectives_collection: DEFAULT_OBJECTIVES_NAME.to_string(),
+            pool: PoolConfig::default(),
+        }
+    }
+
+    /// Options for an Atlas cluster, reached through its SRV record.
+    pub fn with_credentials(host: &str, user: &str, password: &str) -> Self {
+        Self::new(&format!(
+            "mongodb+srv://{}:{}@{}/?retryWrites=true&w=majority",
+            user, password, host
+        ))
+    }
+}
+
+/// Holds a single `Client` for the whole process. The driver keeps a pool of
+/// connections behind it and monitors the servers, so every clone and every
+/// `get_connection` reuses the same connections.
 #[derive(Debug, Clone)]
 pub struct MongoAdapter {
-    config: MongoAdapterConfig,
+    client: Client,
+    collection: Collection<MatchupOverviewMongo>,
+    events: Collection<MatchupEventMongo>,
+    worlds: Collection<WorldMongo>,
+    objectives: Collection<ObjectiveMongo>,
 }
 
 impl MongoAdapter {
-    pub async fn new(host: &str, user: &str, password: &str) -> Self {
-        let conn_string = format!(
-            "mongodb+srv://{}:{}@{}/?retryWrites=true&w=majority",
-            user, password, host
-        );
+    pub async fn new(host: &str, user: &str, password: &str) -> Result<Self, PersistenceError> {
+        Self::with_pool(host, user, password, &PoolConfig::default()).await
+    }
 
-        let mut client_options = ClientOptions::parse(conn_string)
-            .await
-            .expect("Could not parse connection string");
+    pub async fn with_pool(
+        host: &str,
+        user: &str,
+        password: &str,
+        pool: &PoolConfig,
+    ) -> Result<Self, PersistenceError> {
+        let mut options = MongoOptions::with_credentials(host, user, password);
+        options.pool = pool.clone();
+        Self::with_options(&options).await
+    }
+
+    /// Fails when the connection string is invalid. The deployment itself is
+    /// only reached by the first call that needs it.
+    pub async fn with_options(options: &MongoOptions) -> Result<Self, PersistenceError> {
+        let mut client_options = ClientOptions::parse(&options.uri).await.map_err(|err| {
+            PersistenceError::from(err).context("Invalid Mongo connection string")
+        })?;
 
         let server_api = ServerApi::builder().version(ServerApiVersion::V1).build();
         client_options.server_api = Some(server_api);
 
         // Manually set an option.
-        client_options.app_name = Some("Gw2 WvW Scrapper".to_string());
+        if client_options.app_name.is_none() {
+            client_options.app_name = Some("Gw2 WvW Scrapper".to_string());
+        }
+
+        // Checkouts can't time out on their own, waiting for a server to
+        // answer is the closest the driver has.
+        let pool = &options.pool;
+        client_options.max_pool_size = Some(pool.max_size as u32);
+        client_options.min_pool_size = Some(pool.min_size as u32);
+        client_options.max_idle_time = pool.max_idle;
+        client_options.server_selection_timeout = pool.wait_timeout;
 
         // Get a handle to the deployment.
-        let config = MongoAdapterConfig {
-            mongo_config: client_options,
-        };
-        Self { config }
+        let client = Client::with_options(client_options)?;
+        let database = client.database(&options.database);
+        let collection = database.collection::<MatchupOverviewMongo>(&options.collection);
+        let events = database.collection::<MatchupEventMongo>(&options.events_collection);
+        let worlds = database.collection::<WorldMongo>(&options.worlds_collection);
+        let objectives = database.collection::<ObjectiveMongo>(&options.objectives_collection);
+        Ok(Self {
+            client,
+            collection,
+            events,
+            worlds,
+            objectives,
+        })
     }
 
-    pub async fn get_connection(&self) -> Result<MongoClientAdapter, Box<dyn Error>> {
-        let client = Client::with_options(self.config.mongo_config.clone())?;
-        Ok(MongoClientAdapter::new(client))
+    pub async fn get_connection(&self) -> Result<MongoClientAdapter, PersistenceError> {
+        Ok(MongoClientAdapter::new(
+            self.collection.clone(),
+            self.events.clone(),
+            self.worlds.clone(),
+            self.objectives.clone(),
+        ))
+    }
+
+    /// Round trip to the deployment, to tell whether it can be used.
+    pub async fn ping(&self) -> Result<(), PersistenceError> {
+        self.client
+            .database("admin")
+            .run_command(bson::doc! { "ping": 1 }, None)
+            .await?;
+        Ok(())
+    }
+
+    /// Creates the indexes used to find a matchup, to read date ranges and
+    /// to read events.
+    /// Indexes that already exist are left as they are.
+    pub async fn create_indexes(&self) -> Result<(), PersistenceError> {
+        let indexes = vec![
+            IndexModel::builder()
+                .keys(bson::doc! { "id": 1, "initial_date_matchup": 1 })
+                .build(),
+            IndexModel::builder()
+                .keys(bson::doc! { "end_date_matchup": 1 })
+                .build(),
+        ];
+        self.collection.create_indexes(indexes, None).await?;
+        let event_index = IndexModel::builder()
+            .keys(bson::doc! { "detected_at": 1, "match_id": 1 })
+            .build();
+        self.events.create_index(event_index, None).await?;
+        Ok(())
     }
 }
 
 pub struct MongoClientAdapter {
-    client: Client,
+    collection: Collection<MatchupOverviewMongo>,
+    events: Collection<MatchupEventMongo>,
+    worlds: Collection<WorldMongo>,
+    objectives: Collection<ObjectiveMongo>,
 }
 
 impl MongoClientAdapter {
-    pub fn new(client: Client) -> Self {
-        Self { client }
+    pub fn new(
+        collection: Collection<MatchupOverviewMongo>,
+        events: Collection<MatchupEventMongo>,
+        worlds: Collection<WorldMongo>,
+        objectives: Collection<ObjectiveMongo>,
+    ) -> Self {
+        Self {
+            collection,
+            events,
+            worlds,
+            objectives,
+        }
     }
 }
 
@@ -68,15 +205,13 @@ impl MongoClientAdapter {
     async fn check_exists(
         &self,
         data: &MatchupOverview,
-    ) -> Result<Option<ObjectId>, Box<dyn Error>> {
+    ) -> Result<Option<ObjectId>, PersistenceError> {
         let filter = bson::doc! {
             "id": data.id(),
             "initial_date_matchup": bson::DateTime::from_chrono(*data.start_time())
         };
         let result = self
-            .client
-            .database("gw2-wvw-scrapper")
-            .collection::<MatchupOverviewMongo>("gw2-wvw-scrapper")
+            .collection
             .find_one(filter, None)
             // .find(bson::doc! {}, None)
             .await?;
@@ -86,17 +221,16 @@ impl MongoClientAdapter {
         }
     }
 
-    async fn update(&self, data: &MatchupOverview, id: ObjectId) -> Result<(), Box<dyn Error>> {
-        self.client
-            .database("gw2-wvw-scrapper")
-            .collection::<MatchupOverviewMongo>("gw2-wvw-scrapper")
+    async fn update(&self, data: &MatchupOverview, id: ObjectId) -> Result<(), PersistenceError> {
+        self.collection
             .update_one(
                 bson::doc! {
                   "_id": id,
                 },
                 bson::doc! {
                     "$set": bson::doc!{
-                        "info": bson::to_bson(data).expect("Could not convert data to bson")
+                        "end_date_matchup": bson::DateTime::from_chrono(*data.end_time()),
+                        "info": bson::to_bson(data)?
                     }
                 },
                 UpdateOptions::builder().upsert(true).build(),
@@ -106,16 +240,44 @@ impl MongoClientAdapter {
     }
 }
 
+impl MongoClientAdapter {
+    /// The cursor fetches the results in batches as they are consumed.
+    async fn date_range_cursor(
+        &self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<Cursor<MatchupOverviewMongo>, PersistenceError> {
+        let filter = bson::doc! {
+            "initial_date_matchup": {
+                "$gte": bson::DateTime::from_chrono(*start_date)
+            },
+            "end_date_matchup": {
+                "$lte": bson::DateTime::from_chrono(*end_date)
+            }
+        };
+        let find_options = FindOptions::builder()
+            .sort(bson::doc! { "id": 1, "initial_date_matchup": 1, "_id": 1 })
+            .build();
+        let cursor = self.collection.find(filter, find_options).await?;
+        Ok(cursor)
+    }
+}
+
 #[async_trait]
 impl db_adapter::DbAdapter for MongoClientAdapter {
-    async fn insert(&self, data: &MatchupOverview) -> Result<(), Box<dyn Error>> {
+    #[instrument(skip_all, fields(match_id = %data.id()))]
+    async fn insert(&self, data: &MatchupOverview) -> Result<(), PersistenceError> {
+        let started = Instant::now();
         let existent_id = self.check_exists(data).await?;
         if let Some(id) = existent_id {
-            return self.update(data, id).await;
+            self.update(data, id).await?;
+            debug!(
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "matchup updated"
+            );
+            return Ok(());
         }
-        self.client
-            .database("gw2-wvw-scrapper")
-            .collection::<MatchupOverviewMongo>("gw2-wvw-scrapper")
+        self.collection
             .insert_one(
                 MatchupOverviewMongo {
                     inner_id: ObjectId::new(),
@@ -127,41 +289,307 @@ impl db_adapter::DbAdapter for MongoClientAdapter {
                 None,
             )
             .await?;
+        debug!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "matchup inserted"
+        );
         Ok(())
     }
 
+    #[instrument(skip_all, fields(%start_date, %end_date))]
     async fn select_by_date_range(
         &self,
         start_date: &DateTime<Utc>,
         end_date: &DateTime<Utc>,
-    ) -> Result<Vec<MatchupOverview>, Box<dyn Error>> {
-        let filter = bson::doc! {
-            "initial_date_matchup": {
-                "$gte": bson::DateTime::from_chrono(*start_date)
-            },
-            "end_date_matchup": {
-                "$lte": bson::DateTime::from_chrono(*end_date)
-            }
-        };
-        let find_options = FindOptions::builder()
-            .sort(bson::doc! { "id": 1, "initial_date_matchup": 1, "_id": 1 })
-            .build();
-        let mut cursor = self
-            .client
-            .database("gw2-wvw-scrapper")
-            .collection::<MatchupOverviewMongo>("gw2-wvw-scrapper")
-            .find(filter, find_options)
-            // .find(bson::doc! {}, None)
-            .await?;
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let started = Instant::now();
+        let mut cursor = self.date_range_cursor(start_date, end_date).await?;
 
         let mut matchups: Vec<MatchupOverview> = vec![];
         // Iterate over the results of the cursor.
         while let Some(matchup) = cursor.try_next().await? {
             matchups.push(matchup.info);
         }
+        debug!(
+            matchups = matchups.len(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "matchups selected"
+        );
         return Ok(matchups);
     }
+
+    #[instrument(skip_all, fields(%start_date, %end_date))]
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        let started = Instant::now();
+        let cursor = self.date_range_cursor(start_date, end_date).await?;
+        debug!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "matchup cursor opened"
+        );
+        Ok(cursor
+            .map_ok(|matchup| matchup.info)
+            .map_err(PersistenceError::from)
+            .boxed())
+    }
+
+    #[instrument(skip_all, fields(?query))]
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        // Mongo refuses a $limit of 0.
+        if query.limit == Some(0) {
+            return Ok(vec![]);
+        }
+        let started = Instant::now();
+        let mut cursor = self
+            .collection
+            .aggregate(Self::query_pipeline(query), None)
+            .await?;
+
+        let mut matchups: Vec<MatchupOverview> = vec![];
+        while let Some(document) = cursor.try_next().await? {
+            let matchup: MatchupOverviewMongo = bson::from_document(document)?;
+            matchups.push(matchup.info);
+        }
+        debug!(
+            matchups = matchups.len(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "matchups queried"
+        );
+        Ok(matchups)
+    }
+}
+
+#[async_trait]
+impl db_adapter::EventAdapter for MongoClientAdapter {
+    async fn insert_events(&self, events: &[MatchupEvent]) -> Result<(), PersistenceError> {
+        // Mongo refuses to insert nothing.
+        if events.is_empty() {
+            return Ok(());
+        }
+        let documents = events.iter().map(|event| MatchupEventMongo {
+            inner_id: ObjectId::new(),
+            match_id: event.match_id.clone(),
+            detected_at: bson::DateTime::from_chrono(event.detected_at),
+            event: event.clone(),
+        });
+        self.events.insert_many(documents, None).await?;
+        Ok(())
+    }
+
+    async fn select_events(
+        &self,
+        query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+        if query.limit == Some(0) {
+            return Ok(vec![]);
+        }
+        let mut filter = bson::doc! {};
+        if let Some(match_id) = &query.match_id {
+            filter.insert("match_id", match_id);
+        }
+        let mut detected_at = bson::doc! {};
+        if let Some(from) = query.from {
+            detected_at.insert("$gte", bson::DateTime::from_chrono(from));
+        }
+        if let Some(to) = query.to {
+            detected_at.insert("$lte", bson::DateTime::from_chrono(to));
+        }
+        if !detected_at.is_empty() {
+            filter.insert("detected_at", detected_at);
+        }
+        // Object ids grow with insertion, keeping the events of a capture in
+        // order.
+        let find_options = FindOptions::builder()
+            .sort(bson::doc! { "detected_at": 1, "match_id": 1, "_id": 1 })
+            .limit(query.limit.map(|limit| limit as i64))
+            .build();
+        let mut cursor = self.events.find(filter, find_options).await?;
+
+        let mut events = vec![];
+        while let Some(event) = cursor.try_next().await? {
+            events.push(event.event);
+        }
+        Ok(events)
+    }
+}
+
+#[async_trait]
+impl db_adapter::ReferenceAdapter for MongoClientAdapter {
+    async fn upsert_worlds(&self, worlds: &[World]) -> Result<(), PersistenceError> {
+        for world in worlds {
+            let id = *world.id() as i64;
+            let document = ReferenceMongo {
+                id,
+                info: world.clone(),
+            };
+            self.worlds
+                .replace_one(
+                    bson::doc! { "_id": id },
+                    document,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn select_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+        let find_options = FindOptions::builder().sort(bson::doc! { "_id": 1 }).build();
+        let mut cursor = self.worlds.find(None, find_options).await?;
+        let mut worlds = vec![];
+        while let Some(world) = cursor.try_next().await? {
+            worlds.push(world.info);
+        }
+        Ok(worlds)
+    }
+
+    async fn upsert_objectives(&self, objectives: &[Objective]) -> Result<(), PersistenceError> {
+        for objective in objectives {
+            let document = ReferenceMongo {
+                id: objective.id().clone(),
+                info: objective.clone(),
+            };
+            self.objectives
+                .replace_one(
+                    bson::doc! { "_id": objective.id() },
+                    document,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn select_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+        let find_options = FindOptions::builder().sort(bson::doc! { "_id": 1 }).build();
+        let mut cursor = self.objectives.find(None, find_options).await?;
+        let mut objectives = vec![];
+        while let Some(objective) = cursor.try_next().await? {
+            objectives.push(objective.info);
+        }
+        Ok(objectives)
+    }
+}
+
+impl MongoClientAdapter {
+    fn query_pipeline(query: &MatchupQuery) -> Vec<bson::Document> {
+        let mut conditions: Vec<bson::Document> = vec![];
+        if let Some(match_id) = &query.match_id {
+            conditions.push(bson::doc! { "id": match_id });
+        }
+        if let Some(world_id) = query.world_id {
+            let world_id = world_id as i64;
+            conditions.push(bson::doc! {
+                "$or": [
+                    { "info.all_worlds.red": world_id },
+                    { "info.all_worlds.blue": world_id },
+                    { "info.all_worlds.green": world_id },
+                ]
+            });
+        }
+        let id_pattern = match (query.region, query.tier) {
+            (Some(region), Some(tier)) => Some(format!("^{}-{}$", region.code(), tier)),
+            (Some(region), None) => Some(format!("^{}-[0-9]+$", region.code())),
+            (None, Some(tier)) => Some(format!("^[12]-{}$", tier)),
+            (None, None) => None,
+        };
+        if let Some(pattern) = id_pattern {
+            conditions.push(bson::doc! { "id": { "$regex": pattern } });
+        }
+        match query.time_range {
+            Some(TimeRange::Contained { start, end }) => conditions.push(bson::doc! {
+                "initial_date_matchup": { "$gte": bson::DateTime::from_chrono(start) },
+                "end_date_matchup": { "$lte": bson::DateTime::from_chrono(end) },
+            }),
+            Some(TimeRange::Overlapping { start, end }) => conditions.push(bson::doc! {
+                "initial_date_matchup": { "$lte": bson::DateTime::from_chrono(end) },
+                "end_date_matchup": { "$gte": bson::DateTime::from_chrono(start) },
+            }),
+            None => {}
+        }
+
+        let mut pipeline = vec![];
+        if !conditions.is_empty() {
+            pipeline.push(bson::doc! { "$match": { "$and": conditions } });
+        }
+        if query.latest_per_match {
+            pipeline.push(bson::doc! { "$sort": { "id": 1, "initial_date_matchup": -1 } });
+            pipeline
+                .push(bson::doc! { "$group": { "_id": "$id", "latest": { "$first": "$$ROOT" } } });
+            pipeline.push(bson::doc! { "$replaceRoot": { "newRoot": "$latest" } });
+        }
+        let direction = match query.direction {
+            SortDirection::Ascending => 1,
+            SortDirection::Descending => -1,
+        };
+        let sort = match query.sort_by {
+            SortField::MatchId => bson::doc! { "id": direction, "initial_date_matchup": 1 },
+            SortField::StartTime => {
+                bson::doc! { "initial_date_matchup": direction, "id": 1 }
+            }
+            SortField::EndTime => bson::doc! {
+                "end_date_matchup": direction, "id": 1, "initial_date_matchup": 1
+            },
+        };
+        pipeline.push(bson::doc! { "$sort": sort });
+        if query.offset > 0 {
+            pipeline.push(bson::doc! { "$skip": query.offset as i64 });
+        }
+        if let Some(limit) = query.limit {
+            pipeline.push(bson::doc! { "$limit": limit as i64 });
+        }
+        pipeline
+    }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::{env, error::Error};
+
+    use crate::{
+        conformance,
+        mongo_adapter::{MongoAdapter, MongoOptions},
+    };
+
+    #[tokio::test]
+    async fn uses_the_configured_names() -> Result<(), Box<dyn Error>> {
+        let mut options = MongoOptions::new("mongodb://localhost:27017/?appName=tests");
+        options.database = "archive".to_string();
+        options.collection = "matchups".to_string();
+        let adapter = MongoAdapter::with_options(&options).await?;
+
+        assert_eq!(
+            adapter.collection.namespace().to_string(),
+            "archive.matchups"
+        );
+        assert!(MongoAdapter::with_options(&MongoOptions::new("localhost"))
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a reachable MongoDB, configured through MONGO_URI or MONGO_* variables, \
+               and writes to MONGO_CONFORMANCE_DATABASE"]
+    async fn passes_conformance_suite() -> Result<(), Box<dyn Error>> {
+        let mut options = match env::var("MONGO_URI") {
+            Ok(uri) => MongoOptions::new(&uri),
+            Err(_) => MongoOptions::with_credentials(
+                &env::var("MONGO_HOST")?,
+                &env::var("MONGO_USERNAME")?,
+                &env::var("MONGO_PASSWORD")?,
+            ),
+        };
+        options.database = env::var("MONGO_CONFORMANCE_DATABASE")
+            .unwrap_or_else(|_| "gw2-wvw-scrapper-conformance".to_string());
+        let adapter = MongoAdapter::with_options(&options).await?;
+        adapter.create_indexes().await?;
+        let client = adapter.get_connection().await?;
+        conformance::check_all(&client).await?;
+        conformance::check_events(&client).await?;
+        conformance::check_reference(&client).await
+    }
+}
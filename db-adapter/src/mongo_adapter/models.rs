@@ -1,4 +1,7 @@
-use gw2_api_models::models::matchup_overview::MatchupOverview;
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
 use mongodb::bson::oid::ObjectId;
 use mongodb::bson::DateTime;
 use serde::{Deserialize, Serialize};
@@ -12,3 +15,23 @@ pub struct MatchupOverviewMongo {
     pub end_date_matchup: DateTime,
     pub info: MatchupOverview,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchupEventMongo {
+    #[serde(rename = "_id")]
+    pub inner_id: ObjectId,
+    pub match_id: String,
+    pub detected_at: DateTime,
+    pub event: MatchupEvent,
+}
+
+/// A world or an objective, kept under its API id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferenceMongo<I, T> {
+    #[serde(rename = "_id")]
+    pub id: I,
+    pub info: T,
+}
+
+pub type WorldMongo = ReferenceMongo<i64, World>;
+pub type ObjectiveMongo = ReferenceMongo<String, Objective>;
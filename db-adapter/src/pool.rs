@@ -0,0 +1,118 @@
+use std::{env, error::Error, time::Duration};
+
+use serde::{Deserialize, Deserializer};
+
+/// How many connections an adapter keeps open, and how it checks them.
+///
+/// In config files, durations are given in seconds, with the same names as
+/// the environment variables: `wait_timeout_secs`, `max_idle_secs` and `verify`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolConfig {
+    /// Most connections open at the same time.
+    pub max_size: usize,
+    /// Connections opened ahead of time, when the backend supports it.
+    pub min_size: usize,
+    /// Longest wait for a free connection before failing the call.
+    #[serde(rename = "wait_timeout_secs", deserialize_with = "secs")]
+    pub wait_timeout: Option<Duration>,
+    /// Connections unused for longer than this are closed.
+    #[serde(rename = "max_idle_secs", deserialize_with = "secs")]
+    pub max_idle: Option<Duration>,
+    /// Check a connection still answers before handing it out again.
+    #[serde(rename = "verify")]
+    pub verify_on_checkout: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 10,
+            min_size: 0,
+            wait_timeout: Some(Duration::from_secs(30)),
+            max_idle: Some(Duration::from_secs(10 * 60)),
+            verify_on_checkout: true,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Reads `<PREFIX>_POOL_MAX_SIZE`, `<PREFIX>_POOL_MIN_SIZE`,
+    /// `<PREFIX>_POOL_WAIT_TIMEOUT_SECS`, `<PREFIX>_POOL_MAX_IDLE_SECS` and
+    /// `<PREFIX>_POOL_VERIFY`, keeping the default of the unset ones.
+    pub fn from_env(prefix: &str) -> Result<Self, Box<dyn Error>> {
+        Self::default().with_env(prefix)
+    }
+
+    /// Same as `from_env`, keeping the values of `self` for the unset ones.
+    pub fn with_env(self, prefix: &str) -> Result<Self, Box<dyn Error>> {
+        let mut config = self;
+        if let Some(max_size) = env_value(prefix, "MAX_SIZE")? {
+            config.max_size = max_size;
+        }
+        if let Some(min_size) = env_value(prefix, "MIN_SIZE")? {
+            config.min_size = min_size;
+        }
+        if let Some(secs) = env_value(prefix, "WAIT_TIMEOUT_SECS")? {
+            config.wait_timeout = Some(Duration::from_secs(secs));
+        }
+        if let Some(secs) = env_value(prefix, "MAX_IDLE_SECS")? {
+            config.max_idle = Some(Duration::from_secs(secs));
+        }
+        if let Some(verify) = env_value(prefix, "VERIFY")? {
+            config.verify_on_checkout = verify;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_size == 0 {
+            return Err("Pool max size must be at least 1".to_string());
+        }
+        if self.min_size > self.max_size {
+            return Err(format!(
+                "Pool min size {} is above its max size {}",
+                self.min_size, self.max_size
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_secs))
+}
+
+fn env_value<T>(prefix: &str, name: &str) -> Result<Option<T>, Box<dyn Error>>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    let name = format!("{}_POOL_{}", prefix, name);
+    match env::var(&name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|err| format!("{} is invalid: {}", name, err).into()),
+        Err(_) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PoolConfig;
+
+    #[test]
+    fn rejects_inconsistent_sizes() {
+        let mut config = PoolConfig::default();
+        assert!(config.validate().is_ok());
+
+        config.max_size = 0;
+        assert!(config.validate().is_err());
+
+        config.max_size = 2;
+        config.min_size = 3;
+        assert!(config.validate().is_err());
+    }
+}
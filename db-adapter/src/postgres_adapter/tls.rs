@@ -0,0 +1,91 @@
+use std::{fmt::Display, fs, path::Path, str::FromStr};
+
+use native_tls::{Certificate, TlsConnector};
+use postgres_native_tls::MakeTlsConnector;
+use tokio_postgres::config::SslMode;
+
+use crate::error::PersistenceError;
+
+/// Same modes as libpq's `sslmode`, without `allow` and `verify-ca`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TlsMode {
+    /// Plain connections only.
+    Disable,
+    /// TLS when the server supports it, without checking its certificate.
+    #[default]
+    Prefer,
+    /// TLS always, without checking the server certificate.
+    Require,
+    /// TLS always, with a certificate valid for the host name.
+    VerifyFull,
+}
+
+impl TlsMode {
+    pub(crate) fn ssl_mode(&self) -> SslMode {
+        match self {
+            TlsMode::Disable => SslMode::Disable,
+            TlsMode::Prefer => SslMode::Prefer,
+            TlsMode::Require | TlsMode::VerifyFull => SslMode::Require,
+        }
+    }
+
+    /// Connector for this mode, trusting `ca_certificate` on top of the system
+    /// roots when given.
+    pub(crate) fn connector(
+        &self,
+        ca_certificate: Option<&Path>,
+    ) -> Result<MakeTlsConnector, PersistenceError> {
+        let mut builder = TlsConnector::builder();
+        if let Some(path) = ca_certificate {
+            let pem = fs::read(path).map_err(|err| {
+                PersistenceError::from(err).context(format!("CA certificate {}", path.display()))
+            })?;
+            let certificate = Certificate::from_pem(&pem).map_err(|err| {
+                PersistenceError::Connection(format!(
+                    "Invalid CA certificate {}: {}",
+                    path.display(),
+                    err
+                ))
+            })?;
+            builder.add_root_certificate(certificate);
+        }
+        if *self != TlsMode::VerifyFull {
+            builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+        let connector = builder
+            .build()
+            .map_err(|err| PersistenceError::Connection(format!("TLS setup failed: {}", err)))?;
+        Ok(MakeTlsConnector::new(connector))
+    }
+}
+
+impl Display for TlsMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TlsMode::Disable => "disable",
+            TlsMode::Prefer => "prefer",
+            TlsMode::Require => "require",
+            TlsMode::VerifyFull => "verify-full",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for TlsMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disable" => Ok(TlsMode::Disable),
+            "prefer" => Ok(TlsMode::Prefer),
+            "require" => Ok(TlsMode::Require),
+            "verify-full" => Ok(TlsMode::VerifyFull),
+            other => Err(format!(
+                "Unknown TLS mode \"{}\", expected disable, prefer, require or verify-full",
+                other
+            )),
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use chrono::{DateTime, Utc};
+use gw2_api_models::models::{matchup_event::MatchupEvent, matchup_overview::MatchupOverview};
+
+/// WvW region, the first part of a match id such as `1-3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Region {
+    NorthAmerica,
+    Europe,
+}
+
+impl Region {
+    pub fn code(&self) -> u8 {
+        match self {
+            Region::NorthAmerica => 1,
+            Region::Europe => 2,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Region::NorthAmerica),
+            2 => Some(Region::Europe),
+            _ => None,
+        }
+    }
+
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            Region::NorthAmerica => "na",
+            Region::Europe => "eu",
+        }
+    }
+}
+
+/// Region and tier of a match id such as `1-3`.
+pub fn parse_match_id(match_id: &str) -> Option<(Region, u8)> {
+    let (region, tier) = match_id.split_once('-')?;
+    Some((Region::from_code(region.parse().ok()?)?, tier.parse().ok()?))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeRange {
+    /// Matchups with `start_time >= start` and `end_time <= end`, the same
+    /// rule as `select_by_date_range`.
+    Contained {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+    /// Matchups with `start_time <= end` and `end_time >= start`.
+    Overlapping {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortField {
+    #[default]
+    MatchId,
+    StartTime,
+    EndTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortDirection {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// Which matchups to read, independent of the backend.
+///
+/// Every adapter translates it to its own query language; `apply` is the
+/// reference behaviour, used as is by backends that filter on the client.
+/// Results are sorted by `sort_by`, ties broken by match id and then start
+/// time, always ascending.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MatchupQuery {
+    pub match_id: Option<String>,
+    /// A world in any of the teams, linked worlds included.
+    pub world_id: Option<u64>,
+    pub region: Option<Region>,
+    pub tier: Option<u8>,
+    pub time_range: Option<TimeRange>,
+    /// Keep only the matchup with the latest start time of each match.
+    pub latest_per_match: bool,
+    pub sort_by: SortField,
+    pub direction: SortDirection,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl MatchupQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn match_id(mut self, match_id: &str) -> Self {
+        self.match_id = Some(match_id.to_string());
+        self
+    }
+
+    pub fn world_id(mut self, world_id: u64) -> Self {
+        self.world_id = Some(world_id);
+        self
+    }
+
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    pub fn tier(mut self, tier: u8) -> Self {
+        self.tier = Some(tier);
+        self
+    }
+
+    pub fn contained_in(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.time_range = Some(TimeRange::Contained { start, end });
+        self
+    }
+
+    pub fn overlapping(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        self.time_range = Some(TimeRange::Overlapping { start, end });
+        self
+    }
+
+    pub fn latest_per_match(mut self) -> Self {
+        self.latest_per_match = true;
+        self
+    }
+
+    pub fn sort(mut self, sort_by: SortField, direction: SortDirection) -> Self {
+        self.sort_by = sort_by;
+        self.direction = direction;
+        self
+    }
+
+    pub fn page(mut self, offset: usize, limit: usize) -> Self {
+        self.offset = offset;
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Whether the matchup passes every filter of the query.
+    pub fn matches(&self, matchup: &MatchupOverview) -> bool {
+        if let Some(match_id) = &self.match_id {
+            if matchup.id() != match_id {
+                return false;
+            }
+        }
+        if let Some(world_id) = self.world_id {
+            let teams = matchup.all_worlds();
+            let in_teams = [teams.red(), teams.blue(), teams.green()]
+                .iter()
+                .any(|worlds| worlds.contains(&world_id));
+            if !in_teams {
+                return false;
+            }
+        }
+        if self.region.is_some() || self.tier.is_some() {
+            let Some((region, tier)) = parse_match_id(matchup.id()) else {
+                return false;
+            };
+            if self.region.is_some_and(|wanted| wanted != region)
+                || self.tier.is_some_and(|wanted| wanted != tier)
+            {
+                return false;
+            }
+        }
+        match self.time_range {
+            Some(TimeRange::Contained { start, end }) => {
+                *matchup.start_time() >= start && *matchup.end_time() <= end
+            }
+            Some(TimeRange::Overlapping { start, end }) => {
+                *matchup.start_time() <= end && *matchup.end_time() >= start
+            }
+            None => true,
+        }
+    }
+
+    /// Orders two matchups the way the query asks for.
+    pub fn compare(&self, a: &MatchupOverview, b: &MatchupOverview) -> Ordering {
+        let by_field = match self.sort_by {
+            SortField::MatchId => Ordering::Equal,
+            SortField::StartTime => a.start_time().cmp(b.start_time()),
+            SortField::EndTime => a.end_time().cmp(b.end_time()),
+        };
+        let by_field = match self.direction {
+            SortDirection::Ascending => by_field,
+            SortDirection::Descending => by_field.reverse(),
+        };
+        let by_id = match (self.sort_by, self.direction) {
+            (SortField::MatchId, SortDirection::Descending) => b.id().cmp(a.id()),
+            _ => a.id().cmp(b.id()),
+        };
+        by_field
+            .then(by_id)
+            .then_with(|| a.start_time().cmp(b.start_time()))
+    }
+
+    /// Runs the query over matchups already in memory.
+    pub fn apply<I>(&self, matchups: I) -> Vec<MatchupOverview>
+    where
+        I: IntoIterator<Item = MatchupOverview>,
+    {
+        let filtered = matchups.into_iter().filter(|matchup| self.matches(matchup));
+        let mut result: Vec<MatchupOverview> = if self.latest_per_match {
+            let mut latest: BTreeMap<String, MatchupOverview> = BTreeMap::new();
+            for matchup in filtered {
+                let is_later = latest
+                    .get(matchup.id())
+                    .is_none_or(|stored| matchup.start_time() > stored.start_time());
+                if is_later {
+                    latest.insert(matchup.id().clone(), matchup);
+                }
+            }
+            latest.into_values().collect()
+        } else {
+            filtered.collect()
+        };
+        result.sort_by(|a, b| self.compare(a, b));
+        result
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+/// Which matchup events to read. Results are ordered by detection time,
+/// then match id, events of a same capture in the order they were saved.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EventQuery {
+    pub match_id: Option<String>,
+    /// Events detected at or after it.
+    pub from: Option<DateTime<Utc>>,
+    /// Events detected at or before it.
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl EventQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn match_id(mut self, match_id: &str) -> Self {
+        self.match_id = Some(match_id.to_string());
+        self
+    }
+
+    pub fn detected_between(mut self, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        self.from = Some(from);
+        self.to = Some(to);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn matches(&self, event: &MatchupEvent) -> bool {
+        self.match_id
+            .as_ref()
+            .is_none_or(|match_id| *match_id == event.match_id)
+            && self.from.is_none_or(|from| event.detected_at >= from)
+            && self.to.is_none_or(|to| event.detected_at <= to)
+    }
+
+    /// Runs the query over events already in memory, in the order they were
+    /// saved.
+    pub fn apply<I>(&self, events: I) -> Vec<MatchupEvent>
+    where
+        I: IntoIterator<Item = MatchupEvent>,
+    {
+        let mut result: Vec<MatchupEvent> = events
+            .into_iter()
+            .filter(|event| self.matches(event))
+            .collect();
+        // Stable, so events of a same capture keep their order.
+        result.sort_by(|a, b| {
+            a.detected_at
+                .cmp(&b.detected_at)
+                .then_with(|| a.match_id.cmp(&b.match_id))
+        });
+        result.truncate(self.limit.unwrap_or(usize::MAX));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use gw2_api_models::models::matchup_overview::{mock, MatchupOverview};
+
+    use super::{parse_match_id, MatchupQuery, Region, SortDirection, SortField};
+
+    fn matchup(id: &str, week: i32) -> MatchupOverview {
+        let start =
+            Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap() + Duration::weeks(week.into());
+        mock::get_mock(id, start, start + Duration::weeks(1))
+    }
+
+    #[test]
+    fn parses_region_and_tier() {
+        assert_eq!(parse_match_id("1-3"), Some((Region::NorthAmerica, 3)));
+        assert_eq!(parse_match_id("2-5"), Some((Region::Europe, 5)));
+        assert_eq!(parse_match_id("3-1"), None);
+        assert_eq!(parse_match_id("conformance"), None);
+    }
+
+    #[test]
+    fn overlapping_includes_partial_matchups() {
+        let current = matchup("1-1", 0);
+        let start = *current.start_time() + Duration::days(1);
+        let end = start + Duration::days(1);
+
+        assert!(MatchupQuery::new()
+            .overlapping(start, end)
+            .matches(&current));
+        assert!(!MatchupQuery::new()
+            .contained_in(start, end)
+            .matches(&current));
+    }
+
+    #[test]
+    fn latest_per_match_keeps_last_start() {
+        let query = MatchupQuery::new().latest_per_match();
+        let result = query.apply(vec![
+            matchup("1-1", 0),
+            matchup("1-1", 1),
+            matchup("2-1", 0),
+        ]);
+        assert_eq!(result, vec![matchup("1-1", 1), matchup("2-1", 0)]);
+    }
+
+    #[test]
+    fn sorts_and_paginates() {
+        let query = MatchupQuery::new()
+            .sort(SortField::StartTime, SortDirection::Descending)
+            .page(1, 2);
+        let result = query.apply(vec![
+            matchup("1-1", 0),
+            matchup("1-2", 2),
+            matchup("1-1", 1),
+            matchup("2-1", 2),
+        ]);
+        assert_eq!(result, vec![matchup("2-1", 2), matchup("1-1", 1)]);
+    }
+
+    #[test]
+    fn filters_by_world_region_and_tier() {
+        let mut linked = matchup("2-3", 0);
+        mock::set_all_worlds(&mut linked, vec![2001, 2010], vec![2002], vec![2003]);
+
+        assert!(MatchupQuery::new().world_id(2010).matches(&linked));
+        assert!(!MatchupQuery::new().world_id(1001).matches(&linked));
+        assert!(MatchupQuery::new()
+            .region(Region::Europe)
+            .tier(3)
+            .matches(&linked));
+        assert!(!MatchupQuery::new().tier(1).matches(&linked));
+    }
+}
@@ -0,0 +1,35 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A change noticed between two captures of a match.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct MatchupEvent {
+    pub match_id: String,
+    /// Start time of the matchup the change was seen in.
+    pub start_time: DateTime<Utc>,
+    pub detected_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventKind {
+    /// The match id is reused by a new matchup, after a reset. Matches seen
+    /// for the first time have no previous start time.
+    NewMatchup {
+        previous_start_time: Option<DateTime<Utc>>,
+    },
+    /// The team of a world, known by its main world, comes from another
+    /// match, so another tier.
+    TierChange {
+        world: u64,
+        previous_match_id: String,
+    },
+    /// The worlds linked with a main world changed.
+    Relink {
+        world: u64,
+        previous_worlds: Vec<u64>,
+        worlds: Vec<u64>,
+    },
+}
@@ -3,48 +3,52 @@ use getset::Getters;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[derive(Getters, Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[getset(get = "pub")]
 pub struct Score {
     red: u64,
     blue: u64,
     green: u64,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
 pub struct KillScore {
     red: u64,
     blue: u64,
     green: u64,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[derive(Getters, Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[getset(get = "pub")]
 pub struct World {
     red: u64,
     blue: u64,
     green: u64,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Getters, Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[getset(get = "pub")]
 pub struct Team {
     red: Vec<u64>,
     blue: Vec<u64>,
     green: Vec<u64>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct MapScore {
     r#type: String,
     scores: Score,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct Skirmish {
     id: u64,
     scores: Score,
     map_scores: Vec<MapScore>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Getters, Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[getset(get = "pub")]
 pub struct Objective {
     id: String,
     r#type: String,
@@ -58,13 +62,14 @@ pub struct Objective {
     yaks_delivered: Option<u64>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub struct MapBonus {
     r#type: String,
     owner: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Getters, Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[getset(get = "pub")]
 pub struct MapInfo {
     id: u64,
     r#type: String,
@@ -76,7 +81,7 @@ pub struct MapInfo {
 }
 
 #[serde_as]
-#[derive(Getters, Serialize, Deserialize, Debug, Clone)]
+#[derive(Getters, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[getset(get = "pub")]
 pub struct MatchupOverview {
     id: String,
@@ -133,15 +138,23 @@ mod my_date_format {
 }
 
 pub mod mock {
-    use chrono::Utc;
+    use chrono::{DateTime, Utc};
 
-    use super::{KillScore, MatchupOverview, Score, Team, World};
+    use super::{KillScore, MapInfo, MatchupOverview, Objective, Score, Team, World};
 
     pub fn get_naive_mock() -> MatchupOverview {
+        get_mock("1-1", Utc::now(), Utc::now())
+    }
+
+    pub fn get_mock(
+        id: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> MatchupOverview {
         MatchupOverview {
-            id: String::from("1-1"),
-            start_time: Utc::now(),
-            end_time: Utc::now(),
+            id: String::from(id),
+            start_time,
+            end_time,
             scores: Score {
                 red: 0,
                 blue: 0,
@@ -176,4 +189,55 @@ pub mod mock {
             maps: vec![],
         }
     }
+
+    pub fn set_scores(matchup: &mut MatchupOverview, red: u64, blue: u64, green: u64) {
+        matchup.scores = Score { red, blue, green };
+    }
+
+    pub fn set_worlds(matchup: &mut MatchupOverview, red: u64, blue: u64, green: u64) {
+        matchup.worlds = World { red, blue, green };
+    }
+
+    /// Adds a map holding one objective of `owner`, such as `"Red"`.
+    pub fn add_objective(matchup: &mut MatchupOverview, owner: &str, points_tick: u64) {
+        let none = Score {
+            red: 0,
+            blue: 0,
+            green: 0,
+        };
+        let no_kills = KillScore {
+            red: 0,
+            blue: 0,
+            green: 0,
+        };
+        matchup.maps.push(MapInfo {
+            id: matchup.maps.len() as u64,
+            r#type: "Center".to_string(),
+            scores: none,
+            bonuses: vec![],
+            objectives: vec![Objective {
+                id: format!("{}-{}", matchup.maps.len(), owner),
+                r#type: "Camp".to_string(),
+                owner: owner.to_string(),
+                last_flipped: "2023-05-05T18:00:00Z".to_string(),
+                claimed_by: None,
+                claimed_at: None,
+                points_tick,
+                points_capture: points_tick,
+                guild_upgrades: None,
+                yaks_delivered: None,
+            }],
+            deaths: no_kills,
+            kills: no_kills,
+        });
+    }
+
+    pub fn set_all_worlds(
+        matchup: &mut MatchupOverview,
+        red: Vec<u64>,
+        blue: Vec<u64>,
+        green: Vec<u64>,
+    ) {
+        matchup.all_worlds = Team { red, blue, green };
+    }
 }
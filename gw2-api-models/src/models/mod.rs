@@ -1 +1,4 @@
+pub mod matchup_event;
 pub mod matchup_overview;
+pub mod objective;
+pub mod world;
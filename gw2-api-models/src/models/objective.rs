@@ -0,0 +1,20 @@
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+/// An objective of `/v2/wvw/objectives`, the same in every matchup. Who
+/// holds it is part of the matchup, see `matchup_overview::Objective`.
+#[derive(Getters, Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[getset(get = "pub")]
+pub struct Objective {
+    id: String,
+    name: String,
+    sector_id: u64,
+    r#type: String,
+    map_type: String,
+    map_id: u64,
+    upgrade_id: Option<u64>,
+    coord: Option<[f64; 3]>,
+    label_coord: Option<[f64; 2]>,
+    marker: Option<String>,
+    chat_link: String,
+}
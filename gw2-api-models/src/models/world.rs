@@ -0,0 +1,12 @@
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+/// A world of `/v2/worlds`. Ids starting with 1 are North American worlds,
+/// with 2 European ones.
+#[derive(Getters, Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[getset(get = "pub")]
+pub struct World {
+    id: u64,
+    name: String,
+    population: String,
+}
@@ -1,40 +1,115 @@
-use gw2_api_models::models::matchup_overview::MatchupOverview;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use gw2_api_models::models::{
+    matchup_overview::MatchupOverview, objective::Objective, world::World,
+};
 use reqwest::{Client, ClientBuilder};
+use serde::de::DeserializeOwned;
+use tracing::{debug, instrument, warn};
+
+/// How a request to the API went, as passed to the `on_request` hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiRequest {
+    pub url: String,
+    /// `None` when no response came back.
+    pub status: Option<u16>,
+    pub elapsed: Duration,
+    pub succeeded: bool,
+}
+
+type RequestHook = Arc<dyn Fn(&ApiRequest) + Send + Sync>;
 
 pub struct Gw2ApiWrapper {
     client: Client,
+    on_request: Option<RequestHook>,
 }
 
 impl Gw2ApiWrapper {
     fn build_client() -> Client {
         ClientBuilder::new().build().unwrap()
     }
+
+    /// GETs `url` and decodes its JSON body, logging the status and how long
+    /// it took.
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, reqwest::Error> {
+        let start = Instant::now();
+        let mut status = None;
+        let result = async {
+            let response = self.client.get(url).send().await?;
+            status = Some(response.status().as_u16());
+            response.error_for_status()?.json::<T>().await
+        }
+        .await;
+        let elapsed = start.elapsed();
+        let elapsed_ms = elapsed.as_millis() as u64;
+        match &result {
+            Ok(_) => debug!(url, status, elapsed_ms, "GW2 API answered"),
+            Err(err) => warn!(url, status, elapsed_ms, error = %err, "GW2 API request failed"),
+        }
+        if let Some(on_request) = &self.on_request {
+            on_request(&ApiRequest {
+                url: url.to_string(),
+                status,
+                elapsed,
+                succeeded: result.is_ok(),
+            });
+        }
+        result
+    }
 }
 
 impl Gw2ApiWrapper {
     pub fn create() -> Self {
         Self {
             client: Self::build_client(),
+            on_request: None,
         }
     }
+
+    /// Calls `hook` after every request, failed ones included.
+    pub fn on_request(mut self, hook: impl Fn(&ApiRequest) + Send + Sync + 'static) -> Self {
+        self.on_request = Some(Arc::new(hook));
+        self
+    }
+    #[instrument(skip(self))]
     pub async fn get_matchup_ids(&self) -> Result<Vec<String>, reqwest::Error> {
-        let response = self
-            .client
-            .get("https://api.guildwars2.com/v2/wvw/matches")
-            .send()
+        let data: Vec<String> = self
+            .get_json("https://api.guildwars2.com/v2/wvw/matches")
             .await?;
-        let data: Vec<String> = response.json().await?;
+        debug!(matches = data.len(), "matchup ids fetched");
         Ok(data)
     }
 
+    #[instrument(skip_all, fields(matches = ids.len()))]
     pub async fn get_matchup_info(
         &self,
         ids: Vec<String>,
     ) -> Result<Vec<MatchupOverview>, reqwest::Error> {
         let mut uri = "https://api.guildwars2.com/v2/wvw/matches?ids=".to_owned();
         uri.push_str(&ids.join(","));
-        let response = self.client.get(uri).send().await?;
-        let data: Vec<MatchupOverview> = response.json().await?;
+        let data: Vec<MatchupOverview> = self.get_json(&uri).await?;
+        debug!(matchups = data.len(), "matchups fetched");
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_worlds(&self) -> Result<Vec<World>, reqwest::Error> {
+        let data: Vec<World> = self
+            .get_json("https://api.guildwars2.com/v2/worlds?ids=all")
+            .await?;
+        debug!(worlds = data.len(), "worlds fetched");
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_objectives(&self) -> Result<Vec<Objective>, reqwest::Error> {
+        let data: Vec<Objective> = self
+            .get_json("https://api.guildwars2.com/v2/wvw/objectives?ids=all")
+            .await?;
+        debug!(objectives = data.len(), "objectives fetched");
         Ok(data)
     }
 }
@@ -47,7 +122,6 @@ mod tests {
     async fn can_get_list_of_matchups() {
         let api = Gw2ApiWrapper::create();
         let matchup_ids: Vec<String> = api.get_matchup_ids().await.unwrap();
-        dbg!(&matchup_ids);
         assert_eq!(matchup_ids.len(), 9);
     }
 
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// Reads a date given as RFC 3339, any offset, as in
+/// `2023-05-05T18:00:00+02:00`, or relative to `now`, as in `now`, `now-7d`
+/// or `now+12h`. Relative amounts are in `s`, `m`, `h`, `d` or `w`.
+///
+/// Errors tell what is wrong with `value`, to be shown as is.
+pub fn parse_date(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    match parse(value, now) {
+        // An unescaped `+` arrives as a space in a query string.
+        Err(err) if value.contains(' ') => parse(&value.replace(' ', "+"), now).map_err(|_| err),
+        result => result,
+    }
+}
+
+fn parse(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let Some(offset) = value.strip_prefix("now") else {
+        return DateTime::parse_from_rfc3339(value)
+            .map(|date| date.with_timezone(&Utc))
+            .map_err(|err| {
+                format!(
+                    "\"{}\" is not an RFC 3339 date, as in 2023-05-05T18:00:00Z, \
+                     nor a relative one, as in now-7d: {}",
+                    value, err
+                )
+            });
+    };
+    if offset.is_empty() {
+        return Ok(now);
+    }
+
+    let (later, amount) = match offset.split_at(1) {
+        ("+", amount) => (true, amount),
+        ("-", amount) => (false, amount),
+        _ => {
+            return Err(format!(
+                "\"{}\" must go on with + or - after now, as in now-7d",
+                value
+            ))
+        }
+    };
+    let unit_at = amount
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("\"{}\" has no unit, use s, m, h, d or w", value))?;
+    let (amount, unit) = amount.split_at(unit_at);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("\"{}\" has no amount before its unit", value))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "\"{}\" has an unknown unit \"{}\", use s, m, h, d or w",
+                value, unit
+            ))
+        }
+    };
+    amount
+        .checked_mul(unit_secs)
+        .and_then(|secs| Duration::from_std(std::time::Duration::from_secs(secs)).ok())
+        .and_then(|duration| {
+            if later {
+                now.checked_add_signed(duration)
+            } else {
+                now.checked_sub_signed(duration)
+            }
+        })
+        .ok_or_else(|| format!("\"{}\" is out of range", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::parse_date;
+
+    #[test]
+    fn reads_absolute_and_relative_dates() {
+        let now = Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap();
+        let start = Utc.with_ymd_and_hms(2023, 5, 5, 18, 0, 0).unwrap();
+
+        assert_eq!(parse_date("2023-05-05T18:00:00Z", now), Ok(start));
+        assert_eq!(parse_date("2023-05-05T20:00:00+02:00", now), Ok(start));
+        assert_eq!(parse_date("2023-05-05T20:00:00 02:00", now), Ok(start));
+        assert_eq!(parse_date("now", now), Ok(now));
+        assert_eq!(parse_date("now-7d", now), Ok(start));
+        assert_eq!(parse_date("now+90m", now), Ok(now + Duration::minutes(90)));
+    }
+
+    #[test]
+    fn tells_what_is_wrong() {
+        let now = Utc::now();
+        for (value, problem) in [
+            ("yesterday", "is not an RFC 3339 date"),
+            ("2023-05-05", "is not an RFC 3339 date"),
+            ("now7d", "must go on with + or -"),
+            ("now-7", "has no unit"),
+            ("now-d", "has no amount"),
+            ("now-7y", "unknown unit \"y\""),
+            ("now+99999999999999w", "out of range"),
+        ] {
+            let err = parse_date(value, now).unwrap_err();
+            assert!(err.contains(problem), "{}: {}", value, err);
+        }
+    }
+}
@@ -1,15 +1,38 @@
-use std::env;
+use std::collections::BTreeMap;
 
-use chrono::{NaiveDateTime, ParseError, TimeZone, Utc};
-use gw2_api_models::models::matchup_overview::MatchupOverview;
+use chrono::{DateTime, NaiveDateTime, ParseError, TimeZone, Utc};
+use dates::parse_date;
+use db_adapter::{
+    error::PersistenceError,
+    query::{EventQuery, MatchupQuery},
+};
+use gw2_api_models::models::{matchup_event::MatchupEvent, matchup_overview::MatchupOverview};
 use gw2_info_persistence::{
-    mongo_persistence::MongoPersistence, persistence_system_interface::PersistenceSystem,
+    config::PersistenceConfig,
+    fan_out_persistence::SharedPersistence,
+    health::{check_persistence, HealthReport},
+    persistence_system_interface::PersistenceSystem,
+};
+use rocket::{
+    catch, catchers,
+    form::{self, FromFormField, ValueField},
+    futures::StreamExt,
+    get,
+    http::{ContentType, Status},
+    launch,
+    request::FromParam,
+    response::stream::TextStream,
+    routes,
+    serde::json::{self, Json},
+    Request, State,
 };
-use rocket::{get, http::Status, launch, request::FromParam, routes, serde::json::Json, State};
+use serde::Serialize;
+
+mod dates;
 // use rocket_okapi::{openapi, openapi_get_routes};
 
 struct ServerState {
-    persistence: MongoPersistence,
+    persistence: SharedPersistence,
 }
 
 pub struct NaiveDateForm(pub NaiveDateTime);
@@ -25,6 +48,28 @@ impl<'a> FromParam<'a> for NaiveDateForm {
     }
 }
 
+impl<'v> FromFormField<'v> for NaiveDateForm {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        NaiveDateForm::from_param(field.value)
+            .map_err(|err| form::Error::validation(err.to_string()).into())
+    }
+}
+
+/// Status telling clients whether trying again later is worth it.
+fn error_status(err: &PersistenceError) -> Status {
+    match err {
+        PersistenceError::Connection(_) | PersistenceError::Throttled(_) => {
+            Status::ServiceUnavailable
+        }
+        PersistenceError::Timeout(_) => Status::GatewayTimeout,
+        PersistenceError::NotFound(_) => Status::NotFound,
+        PersistenceError::Conflict(_) => Status::Conflict,
+        PersistenceError::Serialization(_) | PersistenceError::Backend(_) => {
+            Status::InternalServerError
+        }
+    }
+}
+
 #[get("/<start_date>/<end_date>")]
 async fn index(
     start_date: NaiveDateForm,
@@ -41,29 +86,237 @@ async fn index(
 
     match result {
         Ok(data) => Ok(Json(data.to_vec())),
-        Err(_) => Err(Status::InternalServerError),
+        Err(err) => Err(error_status(&err)),
     }
 }
 
+/// Same matchups as `index`, one JSON document per line, sent while they are
+/// read. Errors after the first matchup end the stream with an error line.
+#[get("/<start_date>/<end_date>/stream")]
+async fn index_stream<'a>(
+    start_date: NaiveDateForm,
+    end_date: NaiveDateForm,
+    server_state: &'a State<ServerState>,
+) -> Result<(ContentType, TextStream![String + 'a]), Status> {
+    let start_date = Utc.from_utc_datetime(&start_date.0);
+    let end_date = Utc.from_utc_datetime(&end_date.0);
+
+    // Reaching the backend is checked before answering, while the status can
+    // still tell about it.
+    let mut matchups = server_state
+        .persistence
+        .stream_by_date_range(&start_date, &end_date)
+        .await
+        .map_err(|err| error_status(&err))?;
+    let first = match matchups.next().await {
+        Some(Err(err)) => return Err(error_status(&err)),
+        first => first,
+    };
+
+    let lines = TextStream! {
+        let mut next = first;
+        while let Some(matchup) = next {
+            let line = matchup
+                .map_err(|err| err.to_string())
+                .and_then(|matchup| json::to_string(&matchup).map_err(|err| err.to_string()));
+            match line {
+                Ok(line) => yield line + "\n",
+                Err(err) => {
+                    yield json::json!({ "error": err }).to_string() + "\n";
+                    break;
+                }
+            }
+            next = matchups.next().await;
+        }
+    };
+    Ok((ContentType::new("application", "x-ndjson"), lines))
+}
+
+/// New matchups, tier changes and relinks noticed by the scrapper, oldest
+/// first. Dates use the same format as the matchup routes.
+#[get("/events?<match_id>&<from>&<to>&<limit>")]
+async fn events(
+    match_id: Option<&str>,
+    from: Option<NaiveDateForm>,
+    to: Option<NaiveDateForm>,
+    limit: Option<usize>,
+    server_state: &State<ServerState>,
+) -> Result<Json<Vec<MatchupEvent>>, Status> {
+    let query = EventQuery {
+        match_id: match_id.map(str::to_string),
+        from: from.map(|from| Utc.from_utc_datetime(&from.0)),
+        to: to.map(|to| Utc.from_utc_datetime(&to.0)),
+        limit,
+    };
+
+    match server_state.persistence.query_events(&query).await {
+        Ok(events) => Ok(Json(events)),
+        Err(err) => Err(error_status(&err)),
+    }
+}
+
+/// Body of every `/api/v1` error.
+#[derive(Debug, Serialize)]
+struct ApiError {
+    error: String,
+    /// The query parameter at fault, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parameter: Option<&'static str>,
+}
+
+type ApiResult<T> = Result<Json<T>, (Status, Json<ApiError>)>;
+
+fn api_error(
+    status: Status,
+    error: String,
+    parameter: Option<&'static str>,
+) -> (Status, Json<ApiError>) {
+    (status, Json(ApiError { error, parameter }))
+}
+
+/// Lower and upper bounds standing for a missing `from` or `to`, within what
+/// every backend can store.
+fn open_range() -> (DateTime<Utc>, DateTime<Utc>) {
+    (
+        Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(),
+        Utc.with_ymd_and_hms(9999, 12, 31, 23, 59, 59).unwrap(),
+    )
+}
+
+/// Matchups answered by `/api/v1/matchups` without a `limit`.
+const DEFAULT_MATCHUPS_LIMIT: usize = 500;
+/// Largest `limit` accepted by `/api/v1/matchups`.
+const MAX_MATCHUPS_LIMIT: usize = 5000;
+
+/// Reads a count given as a query parameter, such as `limit`.
+fn parse_count(
+    value: Option<&str>,
+    parameter: &'static str,
+) -> Result<Option<usize>, (Status, Json<ApiError>)> {
+    value
+        .map(|value| {
+            value.parse().map_err(|_| {
+                api_error(
+                    Status::BadRequest,
+                    format!("{}, {}, is not a whole number", parameter, value),
+                    Some(parameter),
+                )
+            })
+        })
+        .transpose()
+}
+
+/// Matchups taking place between `from` and `to`, either of which may be left
+/// out. Dates are RFC 3339, as in `2023-05-05T18:00:00+02:00`, or relative to
+/// now, as in `now-7d`. At most `limit` matchups are answered, 500 unless
+/// given, and no more than 5000, after skipping the first `offset` ones: the
+/// next page starts at `offset + limit`.
+#[get("/matchups?<from>&<to>&<limit>&<offset>")]
+async fn matchups_v1(
+    from: Option<&str>,
+    to: Option<&str>,
+    limit: Option<&str>,
+    offset: Option<&str>,
+    server_state: &State<ServerState>,
+) -> ApiResult<Vec<MatchupOverview>> {
+    let limit = parse_count(limit, "limit")?.unwrap_or(DEFAULT_MATCHUPS_LIMIT);
+    let offset = parse_count(offset, "offset")?.unwrap_or(0);
+    // Backends take the offset as a signed 64 bits integer.
+    if i64::try_from(offset).is_err() {
+        return Err(api_error(
+            Status::BadRequest,
+            format!("offset, {}, is too large", offset),
+            Some("offset"),
+        ));
+    }
+    if limit > MAX_MATCHUPS_LIMIT {
+        return Err(api_error(
+            Status::BadRequest,
+            format!("limit, {}, is above {}", limit, MAX_MATCHUPS_LIMIT),
+            Some("limit"),
+        ));
+    }
+    let now = Utc::now();
+    let parse = |value: Option<&str>, parameter| {
+        value
+            .map(|value| parse_date(value, now))
+            .transpose()
+            .map_err(|err| api_error(Status::BadRequest, err, Some(parameter)))
+    };
+    let from = parse(from, "from")?;
+    let to = parse(to, "to")?;
+
+    let mut query = MatchupQuery::new().page(offset, limit);
+    if from.is_some() || to.is_some() {
+        let (open_start, open_end) = open_range();
+        let (start, end) = (from.unwrap_or(open_start), to.unwrap_or(open_end));
+        if start > end {
+            return Err(api_error(
+                Status::BadRequest,
+                format!(
+                    "from, {}, is after to, {}",
+                    start.to_rfc3339(),
+                    end.to_rfc3339()
+                ),
+                None,
+            ));
+        }
+        query = query.overlapping(start, end);
+    }
+
+    match server_state.persistence.query(&query).await {
+        Ok(matchups) => Ok(Json(matchups)),
+        Err(err) => Err(api_error(error_status(&err), err.to_string(), None)),
+    }
+}
+
+/// Keeps the errors Rocket answers by itself, as unknown routes, in JSON under
+/// `/api/v1`.
+#[catch(default)]
+fn api_v1_catcher(status: Status, _request: &Request<'_>) -> (Status, Json<ApiError>) {
+    let error = status.reason().unwrap_or("Unknown error").to_string();
+    api_error(status, error, None)
+}
+
+/// Liveness: answering is all it takes, a restart does not bring the backends
+/// back.
+#[get("/healthz")]
+fn healthz() -> Json<HealthReport> {
+    Json(HealthReport::new(BTreeMap::new()))
+}
+
+/// Readiness: whether the persistence backend answers.
+#[get("/readyz")]
+async fn readyz(server_state: &State<ServerState>) -> (Status, Json<HealthReport>) {
+    let mut checks = BTreeMap::new();
+    checks.insert(
+        "persistence".to_string(),
+        check_persistence(&server_state.persistence).await,
+    );
+    let report = HealthReport::new(checks);
+    let status = if report.ok {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    (status, Json(report))
+}
+
 #[launch]
 async fn rocket() -> _ {
     dotenv::dotenv().ok();
 
-    let host: &str = &env::var("MONGO_HOST")
-        .expect("MONGO_HOST must be set.")
-        .to_owned();
-    let user: &str = &env::var("MONGO_USERNAME")
-        .expect("MONGO_USERNAME must be set.")
-        .to_owned();
-    let password: &str = &env::var("MONGO_PASSWORD")
-        .expect("MONGO_PASSWORD must be set.")
-        .to_owned();
-
-    let persistence = MongoPersistence::new(host, user, password).await;
+    let persistence = PersistenceConfig::load()
+        .expect("Persistence config must be valid.")
+        .build()
+        .await
+        .expect("Persistence config must be valid.");
 
-    let routes = routes![index];
+    let routes = routes![index, index_stream, events, healthz, readyz];
 
     rocket::build()
         .manage(ServerState { persistence })
         .mount("/", routes)
+        .mount("/api/v1", routes![matchups_v1])
+        .register("/api/v1", catchers![api_v1_catcher])
 }
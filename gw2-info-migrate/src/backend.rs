@@ -0,0 +1,63 @@
+use std::{error::Error, path::Path, sync::Arc};
+
+use gw2_info_persistence::{
+    config::{Backend, PersistenceConfig},
+    fan_out_persistence::SharedPersistence,
+    file_format::FileFormat,
+    file_layout::FileLayout,
+    file_system_persistence::FileSystemPersistence,
+};
+
+/// Builds a persistence from a backend description:
+/// - `mongo`, `postgres` or `dynamo`, configured by the same environment
+///   variables as `PersistenceConfig`, without reading a config file;
+/// - `file:<path>[,format=<format>][,layout=<layout>]`;
+/// - `config[:<path>]`, the backend selected in a config file, `GW2_CONFIG`
+///   when no path is given.
+pub async fn from_spec(spec: &str) -> Result<SharedPersistence, Box<dyn Error>> {
+    if let Some(options) = spec.strip_prefix("file:") {
+        return file_from_options(options);
+    }
+    if spec == "config" {
+        return PersistenceConfig::load()?.build().await;
+    }
+    if let Some(path) = spec.strip_prefix("config:") {
+        return PersistenceConfig::from_file(Path::new(path))?
+            .with_env()?
+            .build()
+            .await;
+    }
+    let backend = match spec {
+        "mongo" => Backend::Mongo,
+        "postgres" => Backend::Postgres,
+        "dynamo" => Backend::Dynamo,
+        other => return Err(format!("Unknown backend \"{}\"", other).into()),
+    };
+    // Set after the environment, so `PERSISTENCE_BACKEND` can't replace it.
+    let mut config = PersistenceConfig::default().with_env()?;
+    config.backend = backend;
+    config.build().await
+}
+
+fn file_from_options(options: &str) -> Result<SharedPersistence, Box<dyn Error>> {
+    let mut parts = options.split(',');
+    let basepath = parts.next().unwrap_or_default();
+    if basepath.is_empty() {
+        return Err("File backend needs a path, as in file:<path>".into());
+    }
+
+    let mut format = FileFormat::default();
+    let mut layout = FileLayout::default();
+    for part in parts {
+        match part.split_once('=') {
+            Some(("format", value)) => format = value.parse()?,
+            Some(("layout", value)) => layout = value.parse()?,
+            _ => return Err(format!("Unknown file backend option \"{}\"", part).into()),
+        }
+    }
+    Ok(Arc::new(FileSystemPersistence::with_layout(
+        basepath.to_string(),
+        format,
+        layout,
+    )))
+}
@@ -0,0 +1,97 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use chrono::{DateTime, Duration, Utc};
+use clap::Parser;
+use gw2_info_persistence::migration::{Migration, MigrationOptions};
+
+mod backend;
+
+/// Copies matchups between persistence backends.
+///
+/// Backends are `mongo`, `postgres`, `dynamo`,
+/// `file:<path>[,format=<format>][,layout=<layout>]` or `config[:<path>]`.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Backend to copy from.
+    #[arg(long)]
+    source: String,
+    /// Backend to copy to.
+    #[arg(long)]
+    target: String,
+    /// Copy matchups starting at or after this date (RFC 3339).
+    #[arg(long)]
+    from: DateTime<Utc>,
+    /// Copy matchups starting before this date (RFC 3339).
+    #[arg(long)]
+    to: DateTime<Utc>,
+    /// Length of each batch, in hours.
+    #[arg(long, default_value_t = 24 * 7)]
+    batch_hours: i64,
+    /// Longest a matchup can last, in hours.
+    #[arg(long, default_value_t = 24 * 8)]
+    max_matchup_hours: i64,
+    /// File keeping the progress, to resume an interrupted migration.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    /// Skip comparing both backends once the copy is done.
+    #[arg(long)]
+    no_verify: bool,
+}
+
+const EXIT_FAILED: u8 = 1;
+const EXIT_VERIFICATION_FAILED: u8 = 3;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    dotenv::dotenv().ok();
+    let args = Args::parse();
+
+    let (source, target) = match (
+        backend::from_spec(&args.source).await,
+        backend::from_spec(&args.target).await,
+    ) {
+        (Ok(source), Ok(target)) => (source, target),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("Could not open backend: {}", err);
+            return ExitCode::from(EXIT_FAILED);
+        }
+    };
+
+    let mut options = MigrationOptions::new(args.from, args.to);
+    options.batch_length = Duration::hours(args.batch_hours);
+    options.max_matchup_length = Duration::hours(args.max_matchup_hours);
+    options.checkpoint = args.checkpoint;
+    options.verify = !args.no_verify;
+
+    let report = match Migration::new(&*source, &*target, options).run().await {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Migration stopped: {}", err);
+            return ExitCode::from(EXIT_FAILED);
+        }
+    };
+
+    if let Some(resumed_from) = report.resumed_from {
+        println!("Resumed from {}", resumed_from.to_rfc3339());
+    }
+    println!(
+        "{} batches: {} read, {} inserted, {} updated, {} skipped",
+        report.batches, report.read, report.inserted, report.updated, report.skipped
+    );
+    if let Some(verification) = report.verification {
+        println!(
+            "Verification: {} in source, {} in target, {} mismatches",
+            verification.source_count,
+            verification.target_count,
+            verification.mismatches.len()
+        );
+        for (id, start_time) in verification.mismatches.iter() {
+            println!("  mismatch: {} starting {}", id, start_time.to_rfc3339());
+        }
+        if !verification.is_success() {
+            return ExitCode::from(EXIT_VERIFICATION_FAILED);
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,106 @@
+//! The `db_adapter` conformance suite, run against `PersistenceSystem`
+//! implementations through `AsAdapter`, with `save` standing for `insert`.
+//!
+//! Built for the tests of this crate, and for other crates with the
+//! `conformance` feature.
+
+use std::error::Error;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use db_adapter::{
+    conformance,
+    db_adapter::{DbAdapter, EventAdapter, MatchupStream, ReferenceAdapter},
+    error::PersistenceError,
+    query::{EventQuery, MatchupQuery},
+};
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
+
+use crate::persistence_system_interface::PersistenceSystem;
+
+/// A `PersistenceSystem` seen as the adapters the suite checks.
+pub struct AsAdapter<'a, P: ?Sized>(pub &'a P);
+
+#[async_trait]
+impl<P: PersistenceSystem + Send + Sync + ?Sized> DbAdapter for AsAdapter<'_, P> {
+    async fn insert(&self, obj: &MatchupOverview) -> Result<(), PersistenceError> {
+        self.0.save(std::slice::from_ref(obj)).await
+    }
+
+    async fn select_by_date_range(
+        &self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        self.0.select_by_date_range(start_date, end_date).await
+    }
+
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        self.0.stream_by_date_range(start_date, end_date).await
+    }
+
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        self.0.query(query).await
+    }
+}
+
+#[async_trait]
+impl<P: PersistenceSystem + Send + Sync + ?Sized> EventAdapter for AsAdapter<'_, P> {
+    async fn insert_events(&self, events: &[MatchupEvent]) -> Result<(), PersistenceError> {
+        self.0.save_events(events).await
+    }
+
+    async fn select_events(
+        &self,
+        query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+        self.0.query_events(query).await
+    }
+}
+
+#[async_trait]
+impl<P: PersistenceSystem + Send + Sync + ?Sized> ReferenceAdapter for AsAdapter<'_, P> {
+    async fn upsert_worlds(&self, worlds: &[World]) -> Result<(), PersistenceError> {
+        self.0.save_worlds(worlds).await
+    }
+
+    async fn select_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+        self.0.query_worlds().await
+    }
+
+    async fn upsert_objectives(&self, objectives: &[Objective]) -> Result<(), PersistenceError> {
+        self.0.save_objectives(objectives).await
+    }
+
+    async fn select_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+        self.0.query_objectives().await
+    }
+}
+
+pub async fn check_all<P>(persistence: &P) -> Result<(), Box<dyn Error>>
+where
+    P: PersistenceSystem + Send + Sync + ?Sized,
+{
+    conformance::check_all(&AsAdapter(persistence)).await
+}
+
+pub async fn check_events<P>(persistence: &P) -> Result<(), Box<dyn Error>>
+where
+    P: PersistenceSystem + Send + Sync + ?Sized,
+{
+    conformance::check_events(&AsAdapter(persistence)).await
+}
+
+pub async fn check_reference<P>(persistence: &P) -> Result<(), Box<dyn Error>>
+where
+    P: PersistenceSystem + Send + Sync + ?Sized,
+{
+    conformance::check_reference(&AsAdapter(persistence)).await
+}
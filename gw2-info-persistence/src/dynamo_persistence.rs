@@ -1,11 +1,18 @@
-use std::error::Error;
-
+use crate::persistence_system_interface::PersistenceSystem;
+use async_stream::try_stream;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use db_adapter::{db_adapter::DbAdapter, dynamo_adapter::DynamoAdapter};
-use gw2_api_models::models::matchup_overview::MatchupOverview;
-
-use crate::persistence_system_interface::PersistenceSystem;
+use db_adapter::{
+    db_adapter::{DbAdapter, MatchupStream},
+    dynamo_adapter::DynamoAdapter,
+    error::PersistenceError,
+    query::{EventQuery, MatchupQuery},
+};
+use futures::StreamExt;
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
 
 #[derive(Debug, Clone)]
 pub struct DynamoPersistence {
@@ -18,11 +25,17 @@ impl DynamoPersistence {
             adapter: DynamoAdapter::new().await,
         }
     }
+
+    pub async fn with_table(table: &str) -> Self {
+        Self {
+            adapter: DynamoAdapter::with_table(table).await,
+        }
+    }
 }
 
 #[async_trait]
 impl PersistenceSystem for DynamoPersistence {
-    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), Box<dyn Error>> {
+    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), PersistenceError> {
         let client = self.adapter.get_connection().await?;
         for o in obj {
             client.insert(o).await?;
@@ -35,10 +48,79 @@ impl PersistenceSystem for DynamoPersistence {
         &self,
         start_date: &DateTime<Utc>,
         end_date: &DateTime<Utc>,
-    ) -> Result<Vec<MatchupOverview>, Box<dyn Error>> {
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
         let client = self.adapter.get_connection().await?;
         let result = client.select_by_date_range(start_date, end_date).await?;
 
         Ok(result)
     }
+
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        let client = self.adapter.get_connection().await?;
+        let (start_date, end_date) = (*start_date, *end_date);
+        // The stream of the adapter borrows the connection, so both go along.
+        Ok(Box::pin(try_stream! {
+            let mut matchups = client
+                .stream_by_date_range(&start_date, &end_date)
+                .await?;
+            while let Some(matchup) = matchups.next().await {
+                yield matchup?;
+            }
+        }))
+    }
+
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let client = self.adapter.get_connection().await?;
+        let result = client.query(query).await?;
+
+        Ok(result)
+    }
+
+    /// Fails, never transiently, so the events aren't spooled either. The
+    /// config asks for `events = false` with DynamoDB.
+    async fn save_events<'life>(
+        &self,
+        _events: &'life [MatchupEvent],
+    ) -> Result<(), PersistenceError> {
+        Err(not_stored("matchup events"))
+    }
+
+    async fn query_events(
+        &self,
+        _query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+        Err(not_stored("matchup events"))
+    }
+
+    async fn save_worlds<'life>(&self, _worlds: &'life [World]) -> Result<(), PersistenceError> {
+        Err(not_stored("worlds"))
+    }
+
+    async fn query_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+        Err(not_stored("worlds"))
+    }
+
+    async fn save_objectives<'life>(
+        &self,
+        _objectives: &'life [Objective],
+    ) -> Result<(), PersistenceError> {
+        Err(not_stored("objectives"))
+    }
+
+    async fn query_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+        Err(not_stored("objectives"))
+    }
+
+    async fn ping(&self) -> Result<(), PersistenceError> {
+        self.adapter.ping().await
+    }
+}
+
+/// The table only has a place for matchups.
+fn not_stored(what: &str) -> PersistenceError {
+    PersistenceError::Backend(format!("DynamoDB does not store {}", what))
 }
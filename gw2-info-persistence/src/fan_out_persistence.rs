@@ -0,0 +1,647 @@
+use std::{fmt::Display, future::Future, sync::Arc, time::Duration};
+
+use async_stream::try_stream;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use db_adapter::{
+    db_adapter::MatchupStream,
+    error::PersistenceError,
+    query::{EventQuery, MatchupQuery},
+};
+use futures::{future::join_all, StreamExt};
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
+use tokio::time::Instant;
+use tracing::{debug, instrument, warn};
+
+use crate::persistence_system_interface::PersistenceSystem;
+
+pub type SharedPersistence = Arc<dyn PersistenceSystem + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkPolicy {
+    /// A failed save fails the whole fan-out save.
+    Required,
+    /// A failed save is only reported.
+    BestEffort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkExecution {
+    /// Saved one at a time, in the order the sinks were given, before the
+    /// parallel sinks.
+    Sequential,
+    /// Saved concurrently with the other parallel sinks.
+    Parallel,
+}
+
+#[derive(Clone)]
+pub struct Sink {
+    name: String,
+    persistence: SharedPersistence,
+    policy: SinkPolicy,
+    execution: SinkExecution,
+}
+
+impl Sink {
+    pub fn new(
+        name: &str,
+        persistence: SharedPersistence,
+        policy: SinkPolicy,
+        execution: SinkExecution,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            persistence,
+            policy,
+            execution,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl std::fmt::Debug for Sink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sink")
+            .field("name", &self.name)
+            .field("policy", &self.policy)
+            .field("execution", &self.execution)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SinkOutcome {
+    pub name: String,
+    pub policy: SinkPolicy,
+    pub elapsed: Duration,
+    /// Why the save failed, if it did.
+    pub error: Option<PersistenceError>,
+}
+
+/// What happened to each sink during a fan-out save, in the order the sinks
+/// were given.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FanOutReport {
+    pub outcomes: Vec<SinkOutcome>,
+}
+
+impl FanOutReport {
+    pub fn failures(&self) -> impl Iterator<Item = &SinkOutcome> {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.error.is_some())
+    }
+
+    pub fn required_failures(&self) -> impl Iterator<Item = &SinkOutcome> {
+        self.failures()
+            .filter(|outcome| outcome.policy == SinkPolicy::Required)
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.required_failures().next().is_none()
+    }
+}
+
+impl Display for FanOutReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let failures: Vec<String> = self
+            .failures()
+            .map(|outcome| {
+                let error = outcome.error.as_ref().map(ToString::to_string);
+                format!(
+                    "{} ({:?}): {}",
+                    outcome.name,
+                    outcome.policy,
+                    error.unwrap_or_default()
+                )
+            })
+            .collect();
+        if failures.is_empty() {
+            write!(f, "all {} sinks saved", self.outcomes.len())
+        } else {
+            write!(f, "failed sinks: {}", failures.join("; "))
+        }
+    }
+}
+
+/// Saves to several persistences at once and reads from a primary one,
+/// falling back to the others in order when it fails.
+#[derive(Debug, Clone)]
+pub struct FanOutPersistence {
+    sinks: Vec<Sink>,
+    primary: usize,
+}
+
+impl FanOutPersistence {
+    /// Reads go to the sink named `primary` first.
+    pub fn new(sinks: Vec<Sink>, primary: &str) -> Result<Self, String> {
+        let primary = sinks
+            .iter()
+            .position(|sink| sink.name == primary)
+            .ok_or_else(|| format!("Primary sink \"{}\" is not one of the sinks", primary))?;
+        Ok(Self { sinks, primary })
+    }
+
+    pub fn sinks(&self) -> &[Sink] {
+        &self.sinks
+    }
+
+    pub async fn save_with_report(&self, obj: &[MatchupOverview]) -> FanOutReport {
+        let mut outcomes: Vec<Option<SinkOutcome>> = vec![None; self.sinks.len()];
+
+        for (index, sink) in self.sinks.iter().enumerate() {
+            if sink.execution == SinkExecution::Sequential {
+                outcomes[index] = Some(Self::save_to(sink, obj).await);
+            }
+        }
+        let parallel = self
+            .sinks
+            .iter()
+            .enumerate()
+            .filter(|(_, sink)| sink.execution == SinkExecution::Parallel)
+            .map(|(index, sink)| async move { (index, Self::save_to(sink, obj).await) });
+        for (index, outcome) in join_all(parallel).await {
+            outcomes[index] = Some(outcome);
+        }
+
+        FanOutReport {
+            outcomes: outcomes.into_iter().flatten().collect(),
+        }
+    }
+
+    async fn save_to(sink: &Sink, obj: &[MatchupOverview]) -> SinkOutcome {
+        let started = Instant::now();
+        let error = sink.persistence.save(obj).await.err();
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match &error {
+            Some(err) => {
+                warn!(sink = %sink.name, policy = ?sink.policy, elapsed_ms, error = %err, "sink failed to save")
+            }
+            None => debug!(sink = %sink.name, elapsed_ms, "sink saved"),
+        }
+        SinkOutcome {
+            name: sink.name.clone(),
+            policy: sink.policy,
+            elapsed: started.elapsed(),
+            error,
+        }
+    }
+
+    /// Logs why each sink failed, and keeps the error of the primary one.
+    fn every_read_failed(errors: Vec<(&str, PersistenceError)>) -> PersistenceError {
+        for (name, err) in errors.iter() {
+            warn!(sink = name, error = %err, "sink failed to read");
+        }
+        let (name, primary_error) = errors
+            .into_iter()
+            .next()
+            .expect("Fan-out has at least its primary sink");
+        primary_error.context(format!("every sink failed to read, sink {}", name))
+    }
+
+    /// Saves to each sink in turn, failing when a required sink fails.
+    async fn save_each<'s, F, Fut>(&'s self, what: &str, save: F) -> Result<(), PersistenceError>
+    where
+        F: Fn(&'s SharedPersistence) -> Fut,
+        Fut: Future<Output = Result<(), PersistenceError>>,
+    {
+        let mut error = None;
+        for sink in self.sinks.iter() {
+            if let Err(err) = save(&sink.persistence).await {
+                warn!(sink = %sink.name, error = %err, "sink failed to save {}", what);
+                if sink.policy == SinkPolicy::Required && error.is_none() {
+                    error = Some(err.context(format!("sink {}", sink.name)));
+                }
+            }
+        }
+        match error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads from the first sink, in read order, that answers.
+    async fn read_first<'s, T, F, Fut>(&'s self, read: F) -> Result<T, PersistenceError>
+    where
+        F: Fn(&'s SharedPersistence) -> Fut,
+        Fut: Future<Output = Result<T, PersistenceError>>,
+    {
+        let mut errors = vec![];
+        for sink in self.read_order() {
+            match read(&sink.persistence).await {
+                Ok(result) => return Ok(result),
+                Err(err) => errors.push((sink.name.as_str(), err)),
+            }
+        }
+        Err(Self::every_read_failed(errors))
+    }
+
+    /// Sinks in read order: the primary one, then the others as given.
+    fn read_order(&self) -> impl Iterator<Item = &Sink> {
+        std::iter::once(&self.sinks[self.primary]).chain(
+            self.sinks
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != self.primary)
+                .map(|(_, sink)| sink),
+        )
+    }
+}
+
+#[async_trait]
+impl PersistenceSystem for FanOutPersistence {
+    #[instrument(skip_all, fields(matchups = obj.len()))]
+    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), PersistenceError> {
+        let report = self.save_with_report(obj).await;
+        // Each failed sink is logged, the caller gets the first error that
+        // made the save fail.
+        let error = report.required_failures().find_map(|failure| {
+            let error = failure.error.clone()?;
+            Some(error.context(format!("sink {}", failure.name)))
+        });
+        match error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    async fn select_by_date_range(
+        &self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let mut errors = vec![];
+        for sink in self.read_order() {
+            match sink
+                .persistence
+                .select_by_date_range(start_date, end_date)
+                .await
+            {
+                Ok(result) => return Ok(result),
+                Err(err) => errors.push((sink.name.as_str(), err)),
+            }
+        }
+        Err(Self::every_read_failed(errors))
+    }
+
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        let (start_date, end_date) = (*start_date, *end_date);
+        Ok(Box::pin(try_stream! {
+            let mut errors = vec![];
+            let mut answered = false;
+            for sink in self.read_order() {
+                let opened = sink
+                    .persistence
+                    .stream_by_date_range(&start_date, &end_date)
+                    .await;
+                let mut matchups = match opened {
+                    Ok(matchups) => matchups,
+                    Err(err) => {
+                        errors.push((sink.name.as_str(), err));
+                        continue;
+                    }
+                };
+                // Falls back only while nothing was yielded, after that the
+                // errors of the sink are the errors of the stream.
+                let first = match matchups.next().await {
+                    Some(Err(err)) => {
+                        errors.push((sink.name.as_str(), err));
+                        continue;
+                    }
+                    first => first,
+                };
+                answered = true;
+                if let Some(first) = first {
+                    yield first?;
+                }
+                while let Some(matchup) = matchups.next().await {
+                    yield matchup?;
+                }
+                break;
+            }
+            if !answered {
+                Err(Self::every_read_failed(errors))?;
+            }
+        }))
+    }
+
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let mut errors = vec![];
+        for sink in self.read_order() {
+            match sink.persistence.query(query).await {
+                Ok(result) => return Ok(result),
+                Err(err) => errors.push((sink.name.as_str(), err)),
+            }
+        }
+        Err(Self::every_read_failed(errors))
+    }
+
+    /// Saved to each sink in turn, with the same policies as matchups.
+    async fn save_events<'life>(
+        &self,
+        events: &'life [MatchupEvent],
+    ) -> Result<(), PersistenceError> {
+        self.save_each("events", |persistence| persistence.save_events(events))
+            .await
+    }
+
+    async fn query_events(
+        &self,
+        query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+        self.read_first(|persistence| persistence.query_events(query))
+            .await
+    }
+
+    /// Saved to each sink in turn, with the same policies as matchups.
+    async fn save_worlds<'life>(&self, worlds: &'life [World]) -> Result<(), PersistenceError> {
+        self.save_each("worlds", |persistence| persistence.save_worlds(worlds))
+            .await
+    }
+
+    async fn query_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+        self.read_first(|persistence| persistence.query_worlds())
+            .await
+    }
+
+    /// Saved to each sink in turn, with the same policies as matchups.
+    async fn save_objectives<'life>(
+        &self,
+        objectives: &'life [Objective],
+    ) -> Result<(), PersistenceError> {
+        self.save_each("objectives", |persistence| {
+            persistence.save_objectives(objectives)
+        })
+        .await
+    }
+
+    async fn query_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+        self.read_first(|persistence| persistence.query_objectives())
+            .await
+    }
+
+    /// Fails when a required sink fails, as a save would.
+    async fn ping(&self) -> Result<(), PersistenceError> {
+        let mut error = None;
+        for sink in self.sinks.iter() {
+            if let Err(err) = sink.persistence.ping().await {
+                warn!(sink = %sink.name, error = %err, "sink failed to answer");
+                if sink.policy == SinkPolicy::Required && error.is_none() {
+                    error = Some(err.context(format!("sink {}", sink.name)));
+                }
+            }
+        }
+        match error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, sync::Arc};
+
+    use async_trait::async_trait;
+    use chrono::{DateTime, Utc};
+    use db_adapter::{
+        db_adapter::MatchupStream,
+        error::PersistenceError,
+        query::{EventQuery, MatchupQuery},
+    };
+    use futures::TryStreamExt;
+    use gw2_api_models::models::{
+        matchup_event::MatchupEvent,
+        matchup_overview::{mock, MatchupOverview},
+        objective::Objective,
+        world::World,
+    };
+
+    use crate::{
+        conformance, in_memory_persistence::InMemoryPersistence,
+        persistence_system_interface::PersistenceSystem,
+    };
+
+    use super::{FanOutPersistence, Sink, SinkExecution, SinkPolicy};
+
+    struct UnreachablePersistence;
+
+    fn unreachable() -> PersistenceError {
+        PersistenceError::Connection("backend is unreachable".to_string())
+    }
+
+    #[async_trait]
+    impl PersistenceSystem for UnreachablePersistence {
+        async fn save<'life>(
+            &self,
+            _obj: &'life [MatchupOverview],
+        ) -> Result<(), PersistenceError> {
+            Err(unreachable())
+        }
+
+        async fn select_by_date_range(
+            &self,
+            _start_date: &DateTime<Utc>,
+            _end_date: &DateTime<Utc>,
+        ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+            Err(unreachable())
+        }
+
+        async fn stream_by_date_range<'a>(
+            &'a self,
+            _start_date: &DateTime<Utc>,
+            _end_date: &DateTime<Utc>,
+        ) -> Result<MatchupStream<'a>, PersistenceError> {
+            Err(unreachable())
+        }
+
+        async fn query(
+            &self,
+            _query: &MatchupQuery,
+        ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+            Err(unreachable())
+        }
+
+        async fn save_events<'life>(
+            &self,
+            _events: &'life [MatchupEvent],
+        ) -> Result<(), PersistenceError> {
+            Err(unreachable())
+        }
+
+        async fn query_events(
+            &self,
+            _query: &EventQuery,
+        ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+            Err(unreachable())
+        }
+
+        async fn save_worlds<'life>(
+            &self,
+            _worlds: &'life [World],
+        ) -> Result<(), PersistenceError> {
+            Err(unreachable())
+        }
+
+        async fn query_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+            Err(unreachable())
+        }
+
+        async fn save_objectives<'life>(
+            &self,
+            _objectives: &'life [Objective],
+        ) -> Result<(), PersistenceError> {
+            Err(unreachable())
+        }
+
+        async fn query_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+            Err(unreachable())
+        }
+
+        async fn ping(&self) -> Result<(), PersistenceError> {
+            Err(unreachable())
+        }
+    }
+
+    fn sink(name: &str, persistence: InMemoryPersistence, policy: SinkPolicy) -> Sink {
+        Sink::new(name, Arc::new(persistence), policy, SinkExecution::Parallel)
+    }
+
+    fn unreachable_sink(name: &str, policy: SinkPolicy) -> Sink {
+        Sink::new(
+            name,
+            Arc::new(UnreachablePersistence),
+            policy,
+            SinkExecution::Sequential,
+        )
+    }
+
+    #[tokio::test]
+    async fn passes_conformance_suite() -> Result<(), Box<dyn Error>> {
+        let persistence = FanOutPersistence::new(
+            vec![
+                sink("first", InMemoryPersistence::new(), SinkPolicy::Required),
+                sink("second", InMemoryPersistence::new(), SinkPolicy::BestEffort),
+            ],
+            "second",
+        )?;
+        conformance::check_all(&persistence).await?;
+        conformance::check_events(&persistence).await?;
+        conformance::check_reference(&persistence).await
+    }
+
+    #[tokio::test]
+    async fn saves_to_every_sink() -> Result<(), Box<dyn Error>> {
+        let first = InMemoryPersistence::new();
+        let second = InMemoryPersistence::new();
+        let persistence = FanOutPersistence::new(
+            vec![
+                sink("first", first.clone(), SinkPolicy::Required),
+                sink("second", second.clone(), SinkPolicy::Required),
+            ],
+            "first",
+        )?;
+
+        let report = persistence
+            .save_with_report(&[mock::get_naive_mock()])
+            .await;
+
+        assert!(report.is_success());
+        assert_eq!(report.outcomes.len(), 2);
+        assert_eq!((first.len(), second.len()), (1, 1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn best_effort_failures_are_only_reported() -> Result<(), Box<dyn Error>> {
+        let stored = InMemoryPersistence::new();
+        let persistence = FanOutPersistence::new(
+            vec![
+                unreachable_sink("archive", SinkPolicy::BestEffort),
+                sink("main", stored.clone(), SinkPolicy::Required),
+            ],
+            "main",
+        )?;
+
+        let report = persistence
+            .save_with_report(&[mock::get_naive_mock()])
+            .await;
+
+        assert!(report.is_success());
+        let failed: Vec<_> = report.failures().map(|outcome| &outcome.name).collect();
+        assert_eq!(failed, vec!["archive"]);
+        assert_eq!(stored.len(), 1);
+        persistence.save(&[mock::get_naive_mock()]).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn required_failures_fail_the_save() -> Result<(), Box<dyn Error>> {
+        let stored = InMemoryPersistence::new();
+        let persistence = FanOutPersistence::new(
+            vec![
+                sink("archive", stored.clone(), SinkPolicy::BestEffort),
+                unreachable_sink("main", SinkPolicy::Required),
+            ],
+            "archive",
+        )?;
+
+        let result = persistence.save(&[mock::get_naive_mock()]).await;
+
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("main"), "{}", message);
+        assert_eq!(stored.len(), 1, "other sinks are still saved");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_fall_back_when_primary_fails() -> Result<(), Box<dyn Error>> {
+        let fallback = InMemoryPersistence::new();
+        let matchup = mock::get_naive_mock();
+        fallback.save(std::slice::from_ref(&matchup)).await?;
+        let persistence = FanOutPersistence::new(
+            vec![
+                sink("fallback", fallback, SinkPolicy::BestEffort),
+                unreachable_sink("main", SinkPolicy::Required),
+            ],
+            "main",
+        )?;
+
+        let result = persistence
+            .select_by_date_range(matchup.start_time(), matchup.end_time())
+            .await?;
+
+        assert_eq!(result, vec![matchup.clone()]);
+
+        let streamed: Vec<MatchupOverview> = persistence
+            .stream_by_date_range(matchup.start_time(), matchup.end_time())
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(streamed, vec![matchup]);
+        Ok(())
+    }
+
+    #[test]
+    fn primary_must_be_a_sink() {
+        let result = FanOutPersistence::new(
+            vec![sink(
+                "main",
+                InMemoryPersistence::new(),
+                SinkPolicy::Required,
+            )],
+            "other",
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use std::{error::Error, fmt::Display, io::Read, str::FromStr};
+
+use db_adapter::error::PersistenceError;
+use gw2_api_models::models::matchup_overview::MatchupOverview;
+
+/// Written before every bincode payload, followed by a version byte, so binary
+/// files can be told apart from JSON ones.
+const BINCODE_MAGIC: &[u8; 4] = b"GW2B";
+const BINCODE_VERSION: u8 = 1;
+const ZSTD_MAGIC: &[u8; 4] = &[0x28, 0xB5, 0x2F, 0xFD];
+const ZSTD_LEVEL: i32 = 3;
+
+/// How a matchup is encoded on disk.
+///
+/// Reading never needs to know the format used to write a file: `decode` looks
+/// at the content itself, so archives written with different formats can live
+/// side by side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileFormat {
+    #[default]
+    PrettyJson,
+    CompactJson,
+    Bincode,
+    JsonZstd,
+    BincodeZstd,
+}
+
+impl FileFormat {
+    pub const ALL: [FileFormat; 5] = [
+        FileFormat::PrettyJson,
+        FileFormat::CompactJson,
+        FileFormat::Bincode,
+        FileFormat::JsonZstd,
+        FileFormat::BincodeZstd,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FileFormat::PrettyJson | FileFormat::CompactJson => "json",
+            FileFormat::Bincode => "bin",
+            FileFormat::JsonZstd => "json.zst",
+            FileFormat::BincodeZstd => "bin.zst",
+        }
+    }
+
+    /// Whether `filename` ends with an extension written by any format.
+    pub fn is_known_file(filename: &str) -> bool {
+        Self::ALL
+            .iter()
+            .any(|format| filename.ends_with(&format!(".{}", format.extension())))
+    }
+
+    /// `path` without the extension of whichever format wrote it.
+    pub fn strip_extension(path: &str) -> Option<&str> {
+        Self::ALL
+            .iter()
+            .find_map(|format| path.strip_suffix(&format!(".{}", format.extension())))
+    }
+
+    pub fn encode(&self, matchup: &MatchupOverview) -> Result<Vec<u8>, PersistenceError> {
+        self.encode_content(matchup)
+            .map_err(|err| PersistenceError::Serialization(err.to_string()))
+    }
+
+    /// Decodes content written by any of the formats.
+    pub fn decode(content: &[u8]) -> Result<MatchupOverview, PersistenceError> {
+        Self::decode_content(content)
+            .map_err(|err| PersistenceError::Serialization(err.to_string()))
+    }
+
+    fn encode_content(&self, matchup: &MatchupOverview) -> Result<Vec<u8>, Box<dyn Error>> {
+        let content = match self {
+            FileFormat::PrettyJson => serde_json::to_vec_pretty(matchup)?,
+            FileFormat::CompactJson => serde_json::to_vec(matchup)?,
+            FileFormat::Bincode => Self::encode_bincode(matchup)?,
+            FileFormat::JsonZstd => {
+                zstd::encode_all(serde_json::to_vec(matchup)?.as_slice(), ZSTD_LEVEL)?
+            }
+            FileFormat::BincodeZstd => {
+                zstd::encode_all(Self::encode_bincode(matchup)?.as_slice(), ZSTD_LEVEL)?
+            }
+        };
+        Ok(content)
+    }
+
+    fn decode_content(content: &[u8]) -> Result<MatchupOverview, Box<dyn Error>> {
+        if content.starts_with(ZSTD_MAGIC) {
+            let mut decompressed = vec![];
+            zstd::Decoder::new(content)?.read_to_end(&mut decompressed)?;
+            return Self::decode_uncompressed(&decompressed);
+        }
+        Self::decode_uncompressed(content)
+    }
+
+    fn decode_uncompressed(content: &[u8]) -> Result<MatchupOverview, Box<dyn Error>> {
+        if let Some(payload) = content.strip_prefix(BINCODE_MAGIC) {
+            return match payload.split_first() {
+                Some((&BINCODE_VERSION, matchup)) => Ok(bincode::deserialize(matchup)?),
+                Some((version, _)) => {
+                    Err(format!("Unsupported bincode file version {}", version).into())
+                }
+                None => Err("Bincode file has no version byte".into()),
+            };
+        }
+        Ok(serde_json::from_slice(content)?)
+    }
+
+    fn encode_bincode(matchup: &MatchupOverview) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut content = BINCODE_MAGIC.to_vec();
+        content.push(BINCODE_VERSION);
+        bincode::serialize_into(&mut content, matchup)?;
+        Ok(content)
+    }
+}
+
+impl Display for FileFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FileFormat::PrettyJson => "pretty-json",
+            FileFormat::CompactJson => "compact-json",
+            FileFormat::Bincode => "bincode",
+            FileFormat::JsonZstd => "json-zstd",
+            FileFormat::BincodeZstd => "bincode-zstd",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for FileFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.to_string() == s)
+            .ok_or_else(|| format!("Unknown file format \"{}\"", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use db_adapter::conformance::detailed_matchup;
+
+    use super::FileFormat;
+
+    #[test]
+    fn every_format_round_trips() {
+        let matchup = detailed_matchup();
+        for format in FileFormat::ALL {
+            let content = format.encode(&matchup).unwrap();
+            let decoded = FileFormat::decode(&content).unwrap();
+            assert_eq!(decoded, matchup, "{} must round trip", format);
+        }
+    }
+
+    #[test]
+    fn compressed_formats_are_smaller() {
+        let matchup = detailed_matchup();
+        let pretty = FileFormat::PrettyJson.encode(&matchup).unwrap();
+        let compressed = FileFormat::JsonZstd.encode(&matchup).unwrap();
+        assert!(compressed.len() < pretty.len());
+    }
+
+    #[test]
+    fn rejects_unknown_bincode_version() {
+        let mut content = FileFormat::Bincode.encode(&detailed_matchup()).unwrap();
+        content[4] = 99;
+        assert!(FileFormat::decode(&content).is_err());
+    }
+
+    #[test]
+    fn parses_its_own_names() {
+        for format in FileFormat::ALL {
+            assert_eq!(format.to_string().parse::<FileFormat>(), Ok(format));
+        }
+        assert!("xml".parse::<FileFormat>().is_err());
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use gw2_api_models::models::matchup_overview::MatchupOverview;
+
+/// Timestamps in paths use the basic ISO-8601 form, without colons or spaces,
+/// so they are valid file names everywhere and still sort chronologically.
+const SAFE_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Placeholder {
+    /// `na` or `eu`, taken from the first part of the match id.
+    Region,
+    /// ISO week of the matchup start, e.g. `2023-W19`.
+    Week,
+    MatchId,
+    StartTime,
+    EndTime,
+    /// When the snapshot was saved.
+    CapturedAt,
+}
+
+impl Placeholder {
+    const ALL: [Placeholder; 6] = [
+        Placeholder::Region,
+        Placeholder::Week,
+        Placeholder::MatchId,
+        Placeholder::StartTime,
+        Placeholder::EndTime,
+        Placeholder::CapturedAt,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Placeholder::Region => "region",
+            Placeholder::Week => "week",
+            Placeholder::MatchId => "match_id",
+            Placeholder::StartTime => "start_time",
+            Placeholder::EndTime => "end_time",
+            Placeholder::CapturedAt => "captured_at",
+        }
+    }
+
+    fn render(&self, matchup: &MatchupOverview, captured_at: &DateTime<Utc>) -> String {
+        match self {
+            Placeholder::Region => region_of(matchup.id()),
+            Placeholder::Week => matchup.start_time().format("%G-W%V").to_string(),
+            Placeholder::MatchId => matchup.id().clone(),
+            Placeholder::StartTime => safe_timestamp(matchup.start_time()),
+            Placeholder::EndTime => safe_timestamp(matchup.end_time()),
+            Placeholder::CapturedAt => safe_timestamp(captured_at),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// Where each snapshot is stored, relative to the persistence base path.
+///
+/// A layout is a template such as `{region}/{week}/{match_id}/{captured_at}`.
+/// The extension of the file format is appended to the rendered path. The
+/// template is also used to read the paths back, so files that do not follow
+/// the layout are ignored and matchups outside a date range can be skipped
+/// without decoding them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLayout {
+    template: String,
+    tokens: Vec<Token>,
+}
+
+/// Values read back from a path that follows a layout.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LayoutMatch {
+    values: HashMap<Placeholder, String>,
+}
+
+impl LayoutMatch {
+    pub fn get(&self, placeholder: Placeholder) -> Option<&str> {
+        self.values.get(&placeholder).map(String::as_str)
+    }
+
+    /// Reads safe timestamps, and the `DateTime` display form the first file
+    /// names used, as in `2023-05-12 18:00:00 UTC`.
+    pub fn timestamp(&self, placeholder: Placeholder) -> Option<DateTime<Utc>> {
+        let value = self.get(placeholder)?;
+        parse_safe_timestamp(value).or_else(|| parse_legacy_timestamp(value))
+    }
+}
+
+impl FileLayout {
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    pub fn has(&self, placeholder: Placeholder) -> bool {
+        self.tokens.contains(&Token::Placeholder(placeholder))
+    }
+
+    /// Path of the snapshot, without the file format extension.
+    pub fn render(&self, matchup: &MatchupOverview, captured_at: &DateTime<Utc>) -> PathBuf {
+        let path: String = self
+            .tokens
+            .iter()
+            .map(|token| match token {
+                Token::Literal(literal) => literal.clone(),
+                Token::Placeholder(placeholder) => {
+                    sanitize(&placeholder.render(matchup, captured_at))
+                }
+            })
+            .collect();
+        PathBuf::from(path)
+    }
+
+    /// Reads the placeholder values back from a relative path, without the file
+    /// format extension. Returns `None` if the path does not follow the layout.
+    pub fn parse(&self, relative_path: &Path) -> Option<LayoutMatch> {
+        let components: Vec<&str> = relative_path
+            .components()
+            .map(|component| component.as_os_str().to_str())
+            .collect::<Option<_>>()?;
+        let mut values = HashMap::new();
+        if Self::match_tokens(&self.tokens, &components.join("/"), &mut values) {
+            Some(LayoutMatch { values })
+        } else {
+            None
+        }
+    }
+
+    fn match_tokens(
+        tokens: &[Token],
+        path: &str,
+        values: &mut HashMap<Placeholder, String>,
+    ) -> bool {
+        match tokens {
+            [] => path.is_empty(),
+            [Token::Literal(literal), rest @ ..] => path
+                .strip_prefix(literal.as_str())
+                .is_some_and(|path| Self::match_tokens(rest, path, values)),
+            [Token::Placeholder(placeholder), rest @ ..] => {
+                // Placeholders never span directories, and the template parser
+                // guarantees the next token, if any, is a literal.
+                let segment_end = path.find('/').unwrap_or(path.len());
+                let value_end = match rest.first() {
+                    Some(Token::Literal(literal)) => {
+                        let next = literal.split('/').next().unwrap_or_default();
+                        if next.is_empty() {
+                            segment_end
+                        } else {
+                            match path[..segment_end].find(next) {
+                                Some(end) => end,
+                                None => return false,
+                            }
+                        }
+                    }
+                    _ => segment_end,
+                };
+                if value_end == 0 {
+                    return false;
+                }
+                values.insert(*placeholder, path[..value_end].to_string());
+                Self::match_tokens(rest, &path[value_end..], values)
+            }
+        }
+    }
+}
+
+impl Default for FileLayout {
+    /// Same names `FileSystemPersistence` has always used, with safe timestamps.
+    fn default() -> Self {
+        "data/match_{match_id}_{start_time}"
+            .parse()
+            .expect("Default layout is valid")
+    }
+}
+
+impl Display for FileLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+impl FromStr for FileLayout {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut tokens = vec![];
+        let mut rest = template;
+        while !rest.is_empty() {
+            match rest.find('{') {
+                Some(0) => {
+                    let close = rest.find('}').ok_or_else(|| {
+                        format!("Unclosed placeholder in layout \"{}\"", template)
+                    })?;
+                    let name = &rest[1..close];
+                    let placeholder = Placeholder::ALL
+                        .into_iter()
+                        .find(|placeholder| placeholder.name() == name)
+                        .ok_or_else(|| format!("Unknown placeholder {{{}}} in layout", name))?;
+                    if let Some(Token::Placeholder(_)) = tokens.last() {
+                        return Err(format!(
+                            "Placeholders must be separated in layout \"{}\"",
+                            template
+                        ));
+                    }
+                    tokens.push(Token::Placeholder(placeholder));
+                    rest = &rest[close + 1..];
+                }
+                Some(open) => {
+                    tokens.push(Token::Literal(rest[..open].to_string()));
+                    rest = &rest[open..];
+                }
+                None => {
+                    tokens.push(Token::Literal(rest.to_string()));
+                    rest = "";
+                }
+            }
+        }
+
+        let layout = Self {
+            template: template.to_string(),
+            tokens,
+        };
+        layout.validate()?;
+        Ok(layout)
+    }
+}
+
+impl FileLayout {
+    fn validate(&self) -> Result<(), String> {
+        let literals = self.tokens.iter().filter_map(|token| match token {
+            Token::Literal(literal) => Some(literal),
+            Token::Placeholder(_) => None,
+        });
+        for literal in literals {
+            if literal.contains('}') {
+                return Err(format!(
+                    "Unopened placeholder in layout \"{}\"",
+                    self.template
+                ));
+            }
+        }
+        if self.template.starts_with('/') || self.template.split('/').any(|part| part == "..") {
+            return Err(format!(
+                "Layout \"{}\" must stay inside the base path",
+                self.template
+            ));
+        }
+        if self.template.ends_with('/') || self.template.contains("//") {
+            return Err(format!(
+                "Layout \"{}\" has an empty path part",
+                self.template
+            ));
+        }
+        let is_unique = self.has(Placeholder::MatchId)
+            && (self.has(Placeholder::StartTime)
+                || self.has(Placeholder::Week)
+                || self.has(Placeholder::CapturedAt));
+        if !is_unique {
+            return Err(format!(
+                "Layout \"{}\" needs {{match_id}} and one of {{start_time}}, {{week}} or {{captured_at}}",
+                self.template
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub fn safe_timestamp(date: &DateTime<Utc>) -> String {
+    date.format(SAFE_TIMESTAMP_FORMAT).to_string()
+}
+
+pub fn parse_safe_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value, SAFE_TIMESTAMP_FORMAT)
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+fn parse_legacy_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.strip_suffix(" UTC")?;
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+/// Region of a match id such as `1-3`: `1` is North America, `2` is Europe.
+pub fn region_of(match_id: &str) -> String {
+    match match_id.split('-').next() {
+        Some("1") => String::from("na"),
+        Some("2") => String::from("eu"),
+        Some(other) if !other.is_empty() => other.to_string(),
+        _ => String::from("unknown"),
+    }
+}
+
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_whitespace() || c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use gw2_api_models::models::matchup_overview::mock;
+
+    use super::{FileLayout, Placeholder};
+
+    #[test]
+    fn renders_default_layout_with_safe_timestamps() {
+        let start = Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap();
+        let matchup = mock::get_mock("1-1", start, start + Duration::days(7));
+        let path = FileLayout::default().render(&matchup, &Utc::now());
+        assert_eq!(path, Path::new("data/match_1-1_20230512T180000Z"));
+    }
+
+    #[test]
+    fn renders_nested_layout() {
+        let start = Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap();
+        let captured_at = Utc.with_ymd_and_hms(2023, 5, 13, 9, 30, 5).unwrap();
+        let matchup = mock::get_mock("2-3", start, start + Duration::days(7));
+        let layout: FileLayout = "{region}/{week}/{match_id}/{captured_at}".parse().unwrap();
+        let path = layout.render(&matchup, &captured_at);
+        assert_eq!(path, Path::new("eu/2023-W19/2-3/20230513T093005Z"));
+    }
+
+    #[test]
+    fn parses_rendered_paths_back() {
+        let start = Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap();
+        let captured_at = Utc.with_ymd_and_hms(2023, 5, 13, 9, 30, 5).unwrap();
+        let matchup = mock::get_mock("1-4", start, start + Duration::days(7));
+        let layout: FileLayout = "{region}/{match_id}/snap_{start_time}_{captured_at}"
+            .parse()
+            .unwrap();
+
+        let values = layout
+            .parse(&layout.render(&matchup, &captured_at))
+            .unwrap();
+
+        assert_eq!(values.get(Placeholder::Region), Some("na"));
+        assert_eq!(values.get(Placeholder::MatchId), Some("1-4"));
+        assert_eq!(values.timestamp(Placeholder::StartTime), Some(start));
+        assert_eq!(values.timestamp(Placeholder::CapturedAt), Some(captured_at));
+    }
+
+    #[test]
+    fn reads_the_first_file_names() {
+        let start = Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap();
+        let values = FileLayout::default()
+            .parse(Path::new("data/match_1-1_2023-05-12 18:00:00 UTC"))
+            .unwrap();
+        assert_eq!(values.get(Placeholder::MatchId), Some("1-1"));
+        assert_eq!(values.timestamp(Placeholder::StartTime), Some(start));
+    }
+
+    #[test]
+    fn ignores_paths_outside_the_layout() {
+        let layout: FileLayout = "{region}/{match_id}/{captured_at}".parse().unwrap();
+        assert!(layout.parse(Path::new("na/1-1")).is_none());
+        assert!(layout.parse(Path::new("na/1-1/a/b")).is_none());
+        assert!(FileLayout::default()
+            .parse(Path::new("other/match_1-1_20230512T180000Z"))
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        for template in [
+            "",
+            "{match_id}",
+            "{region}/{week}",
+            "{match_id}{start_time}",
+            "{match_id}/{unknown}",
+            "{match_id}/{start_time",
+            "/abs/{match_id}_{week}",
+            "../{match_id}_{week}",
+            "{match_id}//{week}",
+        ] {
+            assert!(
+                template.parse::<FileLayout>().is_err(),
+                "\"{}\" must be rejected",
+                template
+            );
+        }
+    }
+}
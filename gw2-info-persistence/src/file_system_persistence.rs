@@ -1,70 +1,578 @@
-use std::{error::Error, fs::File, io::Write};
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
+use async_stream::try_stream;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use gw2_api_models::models::matchup_overview::MatchupOverview;
+use db_adapter::{
+    db_adapter::MatchupStream,
+    error::PersistenceError,
+    query::{EventQuery, MatchupQuery, TimeRange},
+};
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{debug, instrument, warn};
 
-use crate::persistence_system_interface::PersistenceSystem;
+use crate::{
+    file_format::FileFormat,
+    file_layout::{FileLayout, LayoutMatch, Placeholder},
+    persistence_system_interface::PersistenceSystem,
+};
+
+/// Events are appended to this file at the root, one JSON document per line.
+const EVENTS_FILENAME: &str = "events.ndjson";
+/// Worlds and objectives are kept in these files at the root, one JSON
+/// document per line, ordered by id.
+const WORLDS_FILENAME: &str = "worlds.ndjson";
+const OBJECTIVES_FILENAME: &str = "objectives.ndjson";
 
 #[derive(Debug, Clone)]
 pub struct FileSystemPersistence {
-    basepath: String,
+    basepath: PathBuf,
+    format: FileFormat,
+    layout: FileLayout,
+}
+
+/// The latest snapshot of a matchup, already decoded when its path did not
+/// tell which matchup it holds.
+struct Capture {
+    path: PathBuf,
+    matchup: Option<MatchupOverview>,
+}
+
+impl Capture {
+    fn read(self) -> Result<MatchupOverview, PersistenceError> {
+        match self.matchup {
+            Some(matchup) => Ok(matchup),
+            None => FileFormat::decode(&std::fs::read(&self.path)?),
+        }
+    }
 }
 
 #[async_trait]
 impl PersistenceSystem for FileSystemPersistence {
-    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), Box<dyn Error>> {
+    #[instrument(skip_all, fields(matchups = obj.len()))]
+    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), PersistenceError> {
+        let started = Instant::now();
+        let captured_at = Utc::now();
+        let mut files = vec![];
         for wvw_match in obj.iter() {
-            let id = wvw_match.id();
-            let start_time = wvw_match.start_time();
-
-            // save to file named match_{id}_{start_time}.json
-            let filename = Self::gen_filename(id, &start_time.to_string());
-            let mut fp = self.basepath.clone();
-            fp.push('/');
-            fp.push_str(&filename);
-
-            Self::save_json(&fp, serde_json::to_vec_pretty(wvw_match).unwrap().as_ref())?;
+            let mut fp = self
+                .basepath
+                .join(self.layout.render(wvw_match, &captured_at))
+                .into_os_string();
+            fp.push(".");
+            fp.push(self.format.extension());
+            files.push((PathBuf::from(fp), self.format.encode(wvw_match)?));
         }
+        blocking(move || {
+            for (fp, content) in files {
+                Self::save_file(&fp, content.as_ref())?;
+            }
+            Ok(())
+        })
+        .await?;
+        debug!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "matchups saved"
+        );
         Ok(())
     }
+
+    #[instrument(skip_all, fields(%start_date, %end_date))]
     async fn select_by_date_range(
         &self,
-        _start_date: &DateTime<Utc>,
-        _end_date: &DateTime<Utc>,
-    ) -> Result<Vec<MatchupOverview>, Box<dyn Error>> {
-        todo!()
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let started = Instant::now();
+        let query = MatchupQuery::new().contained_in(*start_date, *end_date);
+        let matchups = self.read_latest_captures(&query).await?;
+        let result: Vec<_> = matchups.into_iter().filter(|m| query.matches(m)).collect();
+        debug!(
+            matchups = result.len(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "matchups selected"
+        );
+        Ok(result)
+    }
+
+    #[instrument(skip_all, fields(%start_date, %end_date))]
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        let started = Instant::now();
+        let query = MatchupQuery::new().contained_in(*start_date, *end_date);
+        // The files not decoded while walking the directories are read as
+        // the stream is polled.
+        let captures = {
+            let (persistence, query) = (self.clone(), query.clone());
+            blocking(move || persistence.latest_captures(&query)).await?
+        };
+        let (start_date, end_date) = (*start_date, *end_date);
+        Ok(Box::pin(try_stream! {
+            let mut streamed = 0;
+            for capture in captures {
+                let matchup = match capture.matchup {
+                    Some(matchup) => matchup,
+                    None => FileFormat::decode(&tokio::fs::read(&capture.path).await?)?,
+                };
+                if query.matches(&matchup) {
+                    yield matchup;
+                    streamed += 1;
+                }
+            }
+            debug!(
+                %start_date,
+                %end_date,
+                matchups = streamed,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "matchups streamed"
+            );
+        }))
+    }
+
+    #[instrument(skip_all, fields(?query))]
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let started = Instant::now();
+        let result = query.apply(self.read_latest_captures(query).await?);
+        debug!(
+            matchups = result.len(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "matchups queried"
+        );
+        Ok(result)
+    }
+
+    async fn save_events<'life>(
+        &self,
+        events: &'life [MatchupEvent],
+    ) -> Result<(), PersistenceError> {
+        let mut lines = vec![];
+        for event in events {
+            serde_json::to_writer(&mut lines, event)?;
+            lines.push(b'\n');
+        }
+        let basepath = self.basepath.clone();
+        blocking(move || {
+            std::fs::create_dir_all(&basepath)?;
+            let mut fd = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(basepath.join(EVENTS_FILENAME))?;
+            fd.write_all(&lines)?;
+            fd.sync_data()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn query_events(
+        &self,
+        query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+        let path = self.basepath.join(EVENTS_FILENAME);
+        let events = blocking(move || Self::read_lines(&path)).await?;
+        Ok(query.apply(events))
+    }
+
+    async fn save_worlds<'life>(&self, worlds: &'life [World]) -> Result<(), PersistenceError> {
+        let (path, worlds) = (self.basepath.join(WORLDS_FILENAME), worlds.to_vec());
+        blocking(move || Self::upsert_lines(&path, worlds, |world| *world.id())).await
+    }
+
+    async fn query_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+        let path = self.basepath.join(WORLDS_FILENAME);
+        blocking(move || Self::read_lines(&path)).await
+    }
+
+    async fn save_objectives<'life>(
+        &self,
+        objectives: &'life [Objective],
+    ) -> Result<(), PersistenceError> {
+        let path = self.basepath.join(OBJECTIVES_FILENAME);
+        let objectives = objectives.to_vec();
+        blocking(move || Self::upsert_lines(&path, objectives, |objective| objective.id().clone()))
+            .await
+    }
+
+    async fn query_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+        let path = self.basepath.join(OBJECTIVES_FILENAME);
+        blocking(move || Self::read_lines(&path)).await
+    }
+
+    /// Creates the base directory when missing, as the first save would.
+    async fn ping(&self) -> Result<(), PersistenceError> {
+        let basepath = self.basepath.clone();
+        blocking(move || Ok(std::fs::create_dir_all(basepath)?)).await
     }
 }
 
 impl FileSystemPersistence {
+    /// Decodes every file the query could match, ordered by `(id, start_time)`.
+    async fn read_latest_captures(
+        &self,
+        query: &MatchupQuery,
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let (persistence, query) = (self.clone(), query.clone());
+        blocking(move || {
+            persistence
+                .latest_captures(&query)?
+                .into_iter()
+                .map(Capture::read)
+                .collect()
+        })
+        .await
+    }
+
+    /// Every file the query could match, ordered by `(id, start_time)`. When
+    /// the layout keeps several captures of a matchup, the latest one wins.
+    ///
+    /// The key is read from the path when the layout has it, so a stream can
+    /// go through years of snapshots holding only paths. Otherwise the file is
+    /// decoded for it, and kept decoded.
+    fn latest_captures(&self, query: &MatchupQuery) -> Result<Vec<Capture>, PersistenceError> {
+        let mut captures: BTreeMap<_, (Option<DateTime<Utc>>, Capture)> = BTreeMap::new();
+        let mut off_layout = vec![];
+        for path in Self::list_files(&self.basepath)? {
+            let Some(values) = self.parse_path(&path) else {
+                let is_snapshot = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(FileFormat::is_known_file);
+                if is_snapshot {
+                    off_layout.push(path);
+                }
+                continue;
+            };
+            if !Self::may_match(query, &values) {
+                continue;
+            }
+
+            let (key, matchup) = match (
+                values.get(Placeholder::MatchId),
+                values.timestamp(Placeholder::StartTime),
+            ) {
+                (Some(match_id), Some(start_time)) => ((match_id.to_string(), start_time), None),
+                _ => {
+                    let matchup = FileFormat::decode(&std::fs::read(&path)?)?;
+                    ((matchup.id().clone(), *matchup.start_time()), Some(matchup))
+                }
+            };
+            let captured_at = values.timestamp(Placeholder::CapturedAt);
+            let is_newer = captures
+                .get(&key)
+                .is_none_or(|(stored_at, _)| captured_at >= *stored_at);
+            if is_newer {
+                captures.insert(key, (captured_at, Capture { path, matchup }));
+            }
+        }
+        if let Some(first) = off_layout.first() {
+            warn!(
+                files = off_layout.len(),
+                first = %first.display(),
+                layout = %self.layout,
+                "snapshots not following the layout are ignored"
+            );
+        }
+        Ok(captures.into_values().map(|(_, capture)| capture).collect())
+    }
+
+    /// Skips files whose path already rules them out, without reading them.
+    fn may_match(query: &MatchupQuery, values: &LayoutMatch) -> bool {
+        if let Some(match_id) = &query.match_id {
+            if values
+                .get(Placeholder::MatchId)
+                .is_some_and(|id| id != match_id)
+            {
+                return false;
+            }
+        }
+        let start_time = values.timestamp(Placeholder::StartTime);
+        let end_time = values.timestamp(Placeholder::EndTime);
+        match query.time_range {
+            Some(TimeRange::Contained { start, end }) => {
+                !start_time.is_some_and(|start_time| start_time < start)
+                    && !end_time.is_some_and(|end_time| end_time > end)
+            }
+            Some(TimeRange::Overlapping { start, end }) => {
+                !start_time.is_some_and(|start_time| start_time > end)
+                    && !end_time.is_some_and(|end_time| end_time < start)
+            }
+            None => true,
+        }
+    }
+
     pub fn new(basepath: String) -> Self {
-        Self { basepath }
+        Self::with_format(basepath, FileFormat::default())
     }
+
+    pub fn with_format(basepath: String, format: FileFormat) -> Self {
+        Self::with_layout(basepath, format, FileLayout::default())
+    }
+
+    pub fn with_layout(basepath: String, format: FileFormat, layout: FileLayout) -> Self {
+        Self {
+            basepath: PathBuf::from(basepath),
+            format,
+            layout,
+        }
+    }
+}
+
+/// Runs `f` on the threads kept for blocking work, away from the runtime.
+async fn blocking<T, F>(f: F) -> Result<T, PersistenceError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, PersistenceError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| PersistenceError::Backend(err.to_string()))?
 }
 
 impl FileSystemPersistence {
-    fn save_json(filepath: &String, content: &[u8]) -> Result<(), std::io::Error> {
-        let fp = std::path::Path::new(filepath);
+    /// The JSON documents of the file at `path`, one per line. A missing file
+    /// holds none.
+    fn read_lines<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, PersistenceError> {
+        let fd = match File::open(path) {
+            Ok(fd) => fd,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let mut documents = vec![];
+        for line in BufReader::new(fd).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                documents.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(documents)
+    }
+
+    /// Replaces the documents of the file at `path` having the `key` of one
+    /// of `documents`, and adds the others, keeping them ordered by `key`.
+    fn upsert_lines<K, T, F>(path: &Path, documents: Vec<T>, key: F) -> Result<(), PersistenceError>
+    where
+        K: Ord,
+        T: Serialize + DeserializeOwned,
+        F: Fn(&T) -> K,
+    {
+        let mut merged: BTreeMap<K, T> = Self::read_lines(path)?
+            .into_iter()
+            .map(|document| (key(&document), document))
+            .collect();
+        merged.extend(
+            documents
+                .into_iter()
+                .map(|document| (key(&document), document)),
+        );
+        let mut lines = vec![];
+        for document in merged.values() {
+            serde_json::to_writer(&mut lines, document)?;
+            lines.push(b'\n');
+        }
+        Ok(Self::save_file(path, &lines)?)
+    }
+
+    /// Writes to a temporary file next to the target and renames it, so readers
+    /// never see a partially written snapshot.
+    fn save_file(fp: &Path, content: &[u8]) -> Result<(), std::io::Error> {
         let parent_dir = fp.parent();
         if let Some(pd) = parent_dir {
             if !pd.exists() {
                 std::fs::create_dir_all(pd)?;
             }
-            // dbg!(std::fs::canonicalize(&pd)?);
         }
-        // dbg!(&fp);
-        let mut fd = File::create(fp)?;
+        let mut tmp_name = std::ffi::OsString::from(".");
+        tmp_name.push(fp.file_name().unwrap_or_default());
+        tmp_name.push(".tmp");
+        let tmp_fp = fp.with_file_name(tmp_name);
+
+        let mut fd = File::create(&tmp_fp)?;
         fd.write_all(content)?;
+        fd.sync_all()?;
+        std::fs::rename(&tmp_fp, fp)
+    }
+
+    fn list_files(dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut files = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                files.extend(Self::list_files(&path)?);
+            } else if path.is_file() {
+                files.push(path);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Placeholder values of a snapshot path, `None` when the file was not
+    /// written with this layout.
+    fn parse_path(&self, path: &Path) -> Option<LayoutMatch> {
+        let relative = path.strip_prefix(&self.basepath).ok()?.to_str()?;
+        let without_extension = FileFormat::strip_extension(relative)?;
+        self.layout.parse(Path::new(without_extension))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use db_adapter::query::MatchupQuery;
+    use gw2_api_models::models::matchup_overview::mock;
+
+    use crate::{
+        conformance, file_format::FileFormat, persistence_system_interface::PersistenceSystem,
+    };
+
+    use super::FileSystemPersistence;
+
+    fn persistence_in(dir: &tempfile::TempDir, format: FileFormat) -> FileSystemPersistence {
+        let basepath = dir.path().to_str().unwrap().to_string();
+        FileSystemPersistence::with_format(basepath, format)
+    }
+
+    fn nested_persistence_in(dir: &tempfile::TempDir) -> FileSystemPersistence {
+        let basepath = dir.path().to_str().unwrap().to_string();
+        let layout = "{region}/{week}/{match_id}/{captured_at}".parse().unwrap();
+        FileSystemPersistence::with_layout(basepath, FileFormat::CompactJson, layout)
+    }
+
+    #[tokio::test]
+    async fn passes_conformance_suite_in_every_format() -> Result<(), Box<dyn Error>> {
+        for format in FileFormat::ALL {
+            let dir = tempfile::tempdir()?;
+            let persistence = persistence_in(&dir, format);
+            conformance::check_all(&persistence).await?;
+            conformance::check_events(&persistence).await?;
+            conformance::check_reference(&persistence).await?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn passes_conformance_suite_with_nested_layout() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        conformance::check_all(&nested_persistence_in(&dir)).await
+    }
+
+    #[tokio::test]
+    async fn writes_safe_names_without_leftovers() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let start = Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap();
+        let matchup = mock::get_mock("1-1", start, start + Duration::days(7));
+
+        persistence_in(&dir, FileFormat::PrettyJson)
+            .save(&[matchup])
+            .await?;
+
+        let names: Vec<_> = std::fs::read_dir(dir.path().join("data"))?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(names, vec!["match_1-1_20230512T180000Z.json"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keeps_latest_capture_of_a_matchup() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let start = Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap();
+        let end = start + Duration::days(7);
+        let mut older = mock::get_mock("2-1", start, end);
+        mock::set_scores(&mut older, 1, 1, 1);
+        let mut newer = older.clone();
+        mock::set_scores(&mut newer, 2, 2, 2);
+        let match_dir = dir.path().join("eu/2023-W19/2-1");
+        std::fs::create_dir_all(&match_dir)?;
+        std::fs::write(
+            match_dir.join("20230513T100000Z.json"),
+            FileFormat::CompactJson.encode(&newer)?,
+        )?;
+        std::fs::write(
+            match_dir.join("20230513T090000Z.json"),
+            FileFormat::CompactJson.encode(&older)?,
+        )?;
+        std::fs::write(match_dir.join("notes.txt"), "not a snapshot")?;
+
+        let result = nested_persistence_in(&dir)
+            .select_by_date_range(&start, &end)
+            .await?;
+
+        assert_eq!(result, vec![newer]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_files_named_before_safe_timestamps() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let start = Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap();
+        let end = start + Duration::days(7);
+        let matchup = mock::get_mock("1-1", start, end);
+        std::fs::create_dir(dir.path().join("data"))?;
+        std::fs::write(
+            dir.path()
+                .join("data/match_1-1_2023-05-12 18:00:00 UTC.json"),
+            FileFormat::PrettyJson.encode(&matchup)?,
+        )?;
+        let persistence = persistence_in(&dir, FileFormat::default());
+
+        let result = persistence.select_by_date_range(&start, &end).await?;
+        assert_eq!(result, vec![matchup.clone()]);
+        let later = start + Duration::days(1);
+        let result = persistence
+            .query(&MatchupQuery::new().overlapping(later, later))
+            .await?;
+        assert_eq!(result, vec![matchup]);
         Ok(())
     }
 
-    fn gen_filename(id: &str, start_time: &str) -> String {
-        let mut filename = String::from("data/match_");
-        filename.push_str(id);
-        filename.push('_');
-        filename.push_str(start_time);
-        filename.push_str(".json");
-        filename
+    #[tokio::test]
+    async fn reads_files_written_in_other_formats() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let start = Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap();
+        let end = start + Duration::days(7);
+        let json = mock::get_mock("1-1", start, end);
+        let bincode = mock::get_mock("2-1", start, end);
+
+        persistence_in(&dir, FileFormat::PrettyJson)
+            .save(std::slice::from_ref(&json))
+            .await?;
+        persistence_in(&dir, FileFormat::BincodeZstd)
+            .save(std::slice::from_ref(&bincode))
+            .await?;
+        let result = persistence_in(&dir, FileFormat::CompactJson)
+            .select_by_date_range(&start, &end)
+            .await?;
+
+        assert_eq!(result, vec![json, bincode]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reads_nothing_from_an_empty_directory() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let now = Utc::now();
+        let result = persistence_in(&dir, FileFormat::default())
+            .select_by_date_range(&now, &now)
+            .await?;
+        assert!(result.is_empty());
+        Ok(())
     }
 }
@@ -0,0 +1,113 @@
+//! Health reports shared by the services, one check per dependency:
+//!
+//! ```json
+//! {"ok": false, "checks": {"persistence": {"ok": false, "error": "...", "elapsed_ms": 5000}}}
+//! ```
+
+use std::{collections::BTreeMap, fmt::Display, time::Duration};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio::time::Instant;
+
+use crate::persistence_system_interface::PersistenceSystem;
+
+/// Longest wait for a backend to answer a check.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Whatever else tells about the dependency.
+    #[serde(flatten)]
+    pub detail: Map<String, Value>,
+}
+
+impl Check {
+    pub fn passed() -> Self {
+        Self {
+            ok: true,
+            error: None,
+            detail: Map::new(),
+        }
+    }
+
+    pub fn failed(error: impl Display) -> Self {
+        Self {
+            ok: false,
+            error: Some(error.to_string()),
+            detail: Map::new(),
+        }
+    }
+
+    pub fn with(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.detail.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthReport {
+    /// Whether every check passed.
+    pub ok: bool,
+    pub checks: BTreeMap<String, Check>,
+}
+
+impl HealthReport {
+    pub fn new(checks: BTreeMap<String, Check>) -> Self {
+        Self {
+            ok: checks.values().all(|check| check.ok),
+            checks,
+        }
+    }
+}
+
+/// Pings `persistence`, giving up after `CHECK_TIMEOUT`.
+pub async fn check_persistence<P: PersistenceSystem + ?Sized>(persistence: &P) -> Check {
+    let start = Instant::now();
+    let check = match tokio::time::timeout(CHECK_TIMEOUT, persistence.ping()).await {
+        Ok(Ok(())) => Check::passed(),
+        Ok(Err(err)) => Check::failed(err),
+        Err(_) => Check::failed(format!(
+            "no answer within {} seconds",
+            CHECK_TIMEOUT.as_secs()
+        )),
+    };
+    check.with("elapsed_ms", start.elapsed().as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use crate::in_memory_persistence::InMemoryPersistence;
+
+    use super::{check_persistence, Check, HealthReport};
+
+    #[tokio::test]
+    async fn reports_each_check_and_the_overall_status() {
+        let mut checks = BTreeMap::new();
+        checks.insert(
+            "persistence".to_string(),
+            check_persistence(&InMemoryPersistence::new()).await,
+        );
+        assert!(HealthReport::new(checks.clone()).ok);
+
+        checks.insert(
+            "last_scrape".to_string(),
+            Check::failed("too old").with("age_secs", 3600),
+        );
+        let report = HealthReport::new(checks);
+        assert!(!report.ok);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json["checks"]["last_scrape"],
+            json!({ "ok": false, "error": "too old", "age_secs": 3600 })
+        );
+        assert_eq!(json["checks"]["persistence"]["ok"], json!(true));
+    }
+}
@@ -0,0 +1,142 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use db_adapter::{
+    db_adapter::{DbAdapter, EventAdapter, MatchupStream, ReferenceAdapter},
+    error::PersistenceError,
+    in_memory_adapter::InMemoryAdapter,
+    query::{EventQuery, MatchupQuery},
+};
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
+
+use crate::persistence_system_interface::PersistenceSystem;
+
+/// Keeps every saved matchup in memory, keyed by `(id, start_time)`.
+///
+/// Clones share the same storage, so it can be handed to jobs the same way the
+/// database backed persistences are.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryPersistence {
+    adapter: InMemoryAdapter,
+}
+
+impl InMemoryPersistence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.adapter.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.adapter.is_empty()
+    }
+}
+
+#[async_trait]
+impl PersistenceSystem for InMemoryPersistence {
+    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), PersistenceError> {
+        for o in obj {
+            self.adapter.insert(o).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn select_by_date_range(
+        &self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let result = self
+            .adapter
+            .select_by_date_range(start_date, end_date)
+            .await?;
+
+        Ok(result)
+    }
+
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        self.adapter
+            .stream_by_date_range(start_date, end_date)
+            .await
+    }
+
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let result = self.adapter.query(query).await?;
+
+        Ok(result)
+    }
+
+    async fn save_events<'life>(
+        &self,
+        events: &'life [MatchupEvent],
+    ) -> Result<(), PersistenceError> {
+        self.adapter.insert_events(events).await
+    }
+
+    async fn query_events(
+        &self,
+        query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+        self.adapter.select_events(query).await
+    }
+
+    async fn save_worlds<'life>(&self, worlds: &'life [World]) -> Result<(), PersistenceError> {
+        self.adapter.upsert_worlds(worlds).await
+    }
+
+    async fn query_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+        self.adapter.select_worlds().await
+    }
+
+    async fn save_objectives<'life>(
+        &self,
+        objectives: &'life [Objective],
+    ) -> Result<(), PersistenceError> {
+        self.adapter.upsert_objectives(objectives).await
+    }
+
+    async fn query_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+        self.adapter.select_objectives().await
+    }
+
+    async fn ping(&self) -> Result<(), PersistenceError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use db_adapter::conformance::check_insert;
+
+    use crate::conformance::{self, AsAdapter};
+
+    use super::InMemoryPersistence;
+
+    #[tokio::test]
+    async fn passes_conformance_suite() -> Result<(), Box<dyn Error>> {
+        let persistence = InMemoryPersistence::new();
+        conformance::check_all(&persistence).await?;
+        conformance::check_events(&persistence).await?;
+        conformance::check_reference(&persistence).await
+    }
+
+    #[tokio::test]
+    async fn clones_share_storage() -> Result<(), Box<dyn Error>> {
+        let persistence = InMemoryPersistence::new();
+        let other = persistence.clone();
+        check_insert(&AsAdapter(&other)).await?;
+        assert!(!persistence.is_empty());
+        Ok(())
+    }
+}
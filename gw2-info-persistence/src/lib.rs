@@ -1,5 +1,15 @@
+pub mod config;
+#[cfg(any(test, feature = "conformance"))]
+pub mod conformance;
 pub mod dynamo_persistence;
+pub mod fan_out_persistence;
+pub mod file_format;
+pub mod file_layout;
 pub mod file_system_persistence;
+pub mod health;
+pub mod in_memory_persistence;
+pub mod migration;
 pub mod mongo_persistence;
 pub mod persistence_system_interface;
 pub mod postgres_persistence;
+pub mod spool;
@@ -0,0 +1,383 @@
+//! Copies matchups from one `PersistenceSystem` to another.
+//!
+//! The copy covers every matchup whose `start_time` is in `[from, to)` and goes
+//! through that range in fixed size batches. Since `select_by_date_range` only
+//! returns matchups fully contained in the range, each batch reads a range
+//! extended by `max_matchup_length` and keeps the matchups starting inside the
+//! batch, so matchups crossing a batch boundary are copied exactly once.
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use gw2_api_models::models::matchup_overview::MatchupOverview;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::persistence_system_interface::PersistenceSystem;
+
+type MatchupKey = (String, DateTime<Utc>);
+
+#[derive(Debug, Clone)]
+pub struct MigrationOptions {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub batch_length: Duration,
+    /// Longest a matchup can last. Matchups lasting longer are not copied.
+    pub max_matchup_length: Duration,
+    /// Where progress is kept, so an interrupted migration can be resumed.
+    pub checkpoint: Option<PathBuf>,
+    pub verify: bool,
+}
+
+impl MigrationOptions {
+    pub fn new(from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        Self {
+            from,
+            to,
+            batch_length: Duration::days(7),
+            max_matchup_length: Duration::days(8),
+            checkpoint: None,
+            verify: true,
+        }
+    }
+}
+
+/// Progress of a migration, written after every batch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Every matchup starting before this was copied.
+    pub completed_until: DateTime<Utc>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    pub fn store(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub batches: usize,
+    /// Matchups read from the source.
+    pub read: usize,
+    /// Matchups the target did not have.
+    pub inserted: usize,
+    /// Matchups the target had with a different content.
+    pub updated: usize,
+    /// Matchups the target already had, unchanged.
+    pub skipped: usize,
+    /// When the migration was resumed, where it started from.
+    pub resumed_from: Option<DateTime<Utc>>,
+    pub verification: Option<Verification>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verification {
+    pub source_count: usize,
+    pub target_count: usize,
+    /// Matchups missing from the target or with a different content.
+    pub mismatches: Vec<MatchupKey>,
+}
+
+impl Verification {
+    pub fn is_success(&self) -> bool {
+        self.source_count == self.target_count && self.mismatches.is_empty()
+    }
+}
+
+/// SHA-256 of the JSON representation of the matchup, in hex.
+pub fn content_hash(matchup: &MatchupOverview) -> Result<String, Box<dyn Error>> {
+    let digest = Sha256::digest(serde_json::to_vec(matchup)?);
+    Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+pub struct Migration<'a, S: ?Sized, T: ?Sized> {
+    source: &'a S,
+    target: &'a T,
+    options: MigrationOptions,
+}
+
+impl<'a, S, T> Migration<'a, S, T>
+where
+    S: PersistenceSystem + ?Sized,
+    T: PersistenceSystem + ?Sized,
+{
+    pub fn new(source: &'a S, target: &'a T, options: MigrationOptions) -> Self {
+        Self {
+            source,
+            target,
+            options,
+        }
+    }
+
+    pub async fn run(&self) -> Result<MigrationReport, Box<dyn Error>> {
+        if self.options.batch_length <= Duration::zero() {
+            return Err("Batch length must be positive".into());
+        }
+        let mut report = MigrationReport::default();
+
+        let mut batch_start = self.options.from;
+        if let Some(checkpoint) = self.load_checkpoint()? {
+            batch_start = checkpoint.completed_until;
+            report.resumed_from = Some(batch_start);
+        }
+
+        while batch_start < self.options.to {
+            let batch_end = std::cmp::min(batch_start + self.options.batch_length, self.options.to);
+            self.copy_batch(&batch_start, &batch_end, &mut report)
+                .await?;
+            report.batches += 1;
+            self.store_checkpoint(&batch_end)?;
+            batch_start = batch_end;
+        }
+
+        if self.options.verify {
+            report.verification = Some(self.verify().await?);
+        }
+        Ok(report)
+    }
+
+    async fn copy_batch(
+        &self,
+        batch_start: &DateTime<Utc>,
+        batch_end: &DateTime<Utc>,
+        report: &mut MigrationReport,
+    ) -> Result<(), Box<dyn Error>> {
+        let source = self.read_batch(self.source, batch_start, batch_end).await?;
+        let target = self.read_batch(self.target, batch_start, batch_end).await?;
+        report.read += source.len();
+
+        let mut to_save = vec![];
+        for (key, matchup) in source {
+            match target.get(&key) {
+                None => {
+                    report.inserted += 1;
+                    to_save.push(matchup);
+                }
+                Some(existing) if content_hash(existing)? != content_hash(&matchup)? => {
+                    report.updated += 1;
+                    to_save.push(matchup);
+                }
+                Some(_) => report.skipped += 1,
+            }
+        }
+        if !to_save.is_empty() {
+            self.target.save(&to_save).await?;
+        }
+        Ok(())
+    }
+
+    /// Matchups starting in `[batch_start, batch_end)`.
+    async fn read_batch<P: PersistenceSystem + ?Sized>(
+        &self,
+        persistence: &P,
+        batch_start: &DateTime<Utc>,
+        batch_end: &DateTime<Utc>,
+    ) -> Result<BTreeMap<MatchupKey, MatchupOverview>, Box<dyn Error>> {
+        let read_until = *batch_end + self.options.max_matchup_length;
+        let matchups = persistence
+            .select_by_date_range(batch_start, &read_until)
+            .await?;
+        Ok(matchups
+            .into_iter()
+            .filter(|matchup| matchup.start_time() < batch_end)
+            .map(|matchup| ((matchup.id().clone(), *matchup.start_time()), matchup))
+            .collect())
+    }
+
+    /// Compares counts and content hashes of the whole range on both sides.
+    pub async fn verify(&self) -> Result<Verification, Box<dyn Error>> {
+        let mut verification = Verification::default();
+        let mut batch_start = self.options.from;
+        while batch_start < self.options.to {
+            let batch_end = std::cmp::min(batch_start + self.options.batch_length, self.options.to);
+            let source = self
+                .read_batch(self.source, &batch_start, &batch_end)
+                .await?;
+            let target = self
+                .read_batch(self.target, &batch_start, &batch_end)
+                .await?;
+            verification.source_count += source.len();
+            verification.target_count += target.len();
+            for (key, matchup) in source {
+                let matches = match target.get(&key) {
+                    Some(existing) => content_hash(existing)? == content_hash(&matchup)?,
+                    None => false,
+                };
+                if !matches {
+                    verification.mismatches.push(key);
+                }
+            }
+            batch_start = batch_end;
+        }
+        Ok(verification)
+    }
+
+    /// The stored checkpoint, ignored when it was made for another range.
+    fn load_checkpoint(&self) -> Result<Option<Checkpoint>, Box<dyn Error>> {
+        let Some(path) = &self.options.checkpoint else {
+            return Ok(None);
+        };
+        Ok(Checkpoint::load(path)?.filter(|checkpoint| {
+            checkpoint.from == self.options.from && checkpoint.to == self.options.to
+        }))
+    }
+
+    fn store_checkpoint(&self, completed_until: &DateTime<Utc>) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.options.checkpoint else {
+            return Ok(());
+        };
+        Checkpoint {
+            from: self.options.from,
+            to: self.options.to,
+            completed_until: *completed_until,
+        }
+        .store(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use gw2_api_models::models::matchup_overview::{mock, MatchupOverview};
+
+    use crate::{
+        in_memory_persistence::InMemoryPersistence, persistence_system_interface::PersistenceSystem,
+    };
+
+    use super::{Checkpoint, Migration, MigrationOptions};
+
+    fn reset() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap()
+    }
+
+    /// Two weeks of matchups for two matches, all crossing the daily batches.
+    fn history() -> Vec<MatchupOverview> {
+        let week = Duration::days(7);
+        let mut matchups = vec![];
+        for id in ["1-1", "2-1"] {
+            for n in 0..2 {
+                let start = reset() + week * n;
+                matchups.push(mock::get_mock(id, start, start + week));
+            }
+        }
+        matchups
+    }
+
+    fn options() -> MigrationOptions {
+        let mut options = MigrationOptions::new(reset(), reset() + Duration::days(14));
+        options.batch_length = Duration::days(1);
+        options
+    }
+
+    async fn all(persistence: &InMemoryPersistence) -> Vec<MatchupOverview> {
+        persistence
+            .select_by_date_range(&reset(), &(reset() + Duration::days(21)))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn copies_every_matchup_once() -> Result<(), Box<dyn Error>> {
+        let source = InMemoryPersistence::new();
+        source.save(&history()).await?;
+        let target = InMemoryPersistence::new();
+
+        let report = Migration::new(&source, &target, options()).run().await?;
+
+        assert_eq!(report.batches, 14);
+        assert_eq!((report.read, report.inserted), (4, 4));
+        assert!(report.verification.unwrap().is_success());
+        assert_eq!(all(&target).await, all(&source).await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skips_unchanged_and_updates_changed() -> Result<(), Box<dyn Error>> {
+        let matchups = history();
+        let source = InMemoryPersistence::new();
+        source.save(&matchups).await?;
+        let target = InMemoryPersistence::new();
+        let mut stale = matchups[0].clone();
+        mock::set_scores(&mut stale, 9, 9, 9);
+        target.save(&[stale, matchups[1].clone()]).await?;
+
+        let report = Migration::new(&source, &target, options()).run().await?;
+
+        assert_eq!((report.inserted, report.updated, report.skipped), (2, 1, 1));
+        assert_eq!(all(&target).await, all(&source).await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resumes_from_checkpoint() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let checkpoint_path = dir.path().join("checkpoint.json");
+        let source = InMemoryPersistence::new();
+        source.save(&history()).await?;
+        let target = InMemoryPersistence::new();
+        let mut options = options();
+        options.checkpoint = Some(checkpoint_path.clone());
+        Checkpoint {
+            from: options.from,
+            to: options.to,
+            completed_until: reset() + Duration::days(7),
+        }
+        .store(&checkpoint_path)?;
+
+        let report = Migration::new(&source, &target, options).run().await?;
+
+        assert_eq!(report.resumed_from, Some(reset() + Duration::days(7)));
+        assert_eq!(report.batches, 7);
+        assert_eq!(report.inserted, 2, "the first week was already done");
+        let verification = report.verification.unwrap();
+        assert!(!verification.is_success());
+        assert_eq!(verification.mismatches.len(), 2);
+        assert_eq!(
+            Checkpoint::load(&checkpoint_path)?.unwrap().completed_until,
+            reset() + Duration::days(14)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ignores_checkpoint_of_another_range() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let checkpoint_path = dir.path().join("checkpoint.json");
+        let source = InMemoryPersistence::new();
+        source.save(&history()).await?;
+        let target = InMemoryPersistence::new();
+        let mut options = options();
+        options.checkpoint = Some(checkpoint_path.clone());
+        Checkpoint {
+            from: options.from - Duration::days(7),
+            to: options.to,
+            completed_until: options.to,
+        }
+        .store(&checkpoint_path)?;
+
+        let report = Migration::new(&source, &target, options).run().await?;
+
+        assert_eq!(report.resumed_from, None);
+        assert_eq!(report.inserted, 4);
+        Ok(())
+    }
+}
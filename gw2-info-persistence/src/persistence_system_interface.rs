@@ -1,15 +1,116 @@
-use std::error::Error;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use gw2_api_models::models::matchup_overview::MatchupOverview;
+use db_adapter::{
+    db_adapter::MatchupStream,
+    error::PersistenceError,
+    query::{EventQuery, MatchupQuery},
+};
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
 
 #[async_trait]
 pub trait PersistenceSystem {
-    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), Box<dyn Error>>;
+    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), PersistenceError>;
     async fn select_by_date_range(
         &self,
         start_date: &DateTime<Utc>,
         end_date: &DateTime<Utc>,
-    ) -> Result<Vec<MatchupOverview>, Box<dyn Error>>;
+    ) -> Result<Vec<MatchupOverview>, PersistenceError>;
+    /// Same matchups as `select_by_date_range`, read as they are consumed.
+    /// Failing to reach the backend is an error of the call, later failures
+    /// are items of the stream.
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError>;
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError>;
+    /// Appends the events detected between two captures.
+    async fn save_events<'life>(
+        &self,
+        events: &'life [MatchupEvent],
+    ) -> Result<(), PersistenceError>;
+    async fn query_events(&self, query: &EventQuery)
+        -> Result<Vec<MatchupEvent>, PersistenceError>;
+    /// Replaces the stored worlds with the same ids.
+    async fn save_worlds<'life>(&self, worlds: &'life [World]) -> Result<(), PersistenceError>;
+    /// Every stored world, by id.
+    async fn query_worlds(&self) -> Result<Vec<World>, PersistenceError>;
+    /// Replaces the stored objectives with the same ids.
+    async fn save_objectives<'life>(
+        &self,
+        objectives: &'life [Objective],
+    ) -> Result<(), PersistenceError>;
+    /// Every stored objective, by id.
+    async fn query_objectives(&self) -> Result<Vec<Objective>, PersistenceError>;
+    /// Cheap round trip to the backend, to tell whether it can be used.
+    async fn ping(&self) -> Result<(), PersistenceError>;
+}
+
+#[async_trait]
+impl<P: PersistenceSystem + Send + Sync + ?Sized> PersistenceSystem for Arc<P> {
+    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), PersistenceError> {
+        (**self).save(obj).await
+    }
+
+    async fn select_by_date_range(
+        &self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        (**self).select_by_date_range(start_date, end_date).await
+    }
+
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        (**self).stream_by_date_range(start_date, end_date).await
+    }
+
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        (**self).query(query).await
+    }
+
+    async fn save_events<'life>(
+        &self,
+        events: &'life [MatchupEvent],
+    ) -> Result<(), PersistenceError> {
+        (**self).save_events(events).await
+    }
+
+    async fn query_events(
+        &self,
+        query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+        (**self).query_events(query).await
+    }
+
+    async fn save_worlds<'life>(&self, worlds: &'life [World]) -> Result<(), PersistenceError> {
+        (**self).save_worlds(worlds).await
+    }
+
+    async fn query_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+        (**self).query_worlds().await
+    }
+
+    async fn save_objectives<'life>(
+        &self,
+        objectives: &'life [Objective],
+    ) -> Result<(), PersistenceError> {
+        (**self).save_objectives(objectives).await
+    }
+
+    async fn query_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+        (**self).query_objectives().await
+    }
+
+    async fn ping(&self) -> Result<(), PersistenceError> {
+        (**self).ping().await
+    }
 }
@@ -1,11 +1,21 @@
-use std::error::Error;
+use std::time::Instant;
 
+use async_stream::try_stream;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use db_adapter::{db_adapter::DbAdapter, postgres_adapter::PostgresAdapter};
-use gw2_api_models::models::matchup_overview::MatchupOverview;
-
-use tokio;
+use db_adapter::{
+    db_adapter::{DbAdapter, EventAdapter, MatchupStream, ReferenceAdapter},
+    error::PersistenceError,
+    pool::PoolConfig,
+    postgres_adapter::{PostgresAdapter, PostgresOptions},
+    query::{EventQuery, MatchupQuery},
+};
+use futures::StreamExt;
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
+use tracing::{debug, instrument};
 
 use crate::persistence_system_interface::PersistenceSystem;
 
@@ -15,34 +25,178 @@ pub struct PostgresPersistence {
 }
 
 impl PostgresPersistence {
-    pub fn new(host: &str, user: &str, password: &str) -> Self {
-        Self {
-            adapter: PostgresAdapter::new(host, user, password),
-        }
+    pub fn new(host: &str, user: &str, password: &str) -> Result<Self, PersistenceError> {
+        Ok(Self {
+            adapter: PostgresAdapter::new(host, user, password)?,
+        })
+    }
+
+    pub fn with_pool(
+        host: &str,
+        user: &str,
+        password: &str,
+        pool: &PoolConfig,
+    ) -> Result<Self, PersistenceError> {
+        Ok(Self {
+            adapter: PostgresAdapter::with_pool(host, user, password, pool)?,
+        })
+    }
+
+    pub fn with_options(options: &PostgresOptions) -> Result<Self, PersistenceError> {
+        Ok(Self {
+            adapter: PostgresAdapter::with_options(options)?,
+        })
+    }
+
+    pub async fn create_tables(&self) -> Result<(), PersistenceError> {
+        self.adapter.create_tables().await
     }
 }
 
 #[async_trait]
 impl PersistenceSystem for PostgresPersistence {
-    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), Box<dyn Error>> {
-        let (client, conn) = self.adapter.get_connection().await?;
-
-        tokio::spawn(async move {
-            if let Err(e) = conn.await {
-                eprintln!("connection error: {}", e);
-            }
-        });
+    #[instrument(skip_all, fields(matchups = obj.len()))]
+    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), PersistenceError> {
+        let started = Instant::now();
+        let client = self.adapter.get_connection().await?;
         for o in obj {
             client.insert(o).await?;
         }
-
+        debug!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "matchups saved"
+        );
         Ok(())
     }
+    #[instrument(skip_all, fields(%start_date, %end_date))]
     async fn select_by_date_range(
         &self,
-        _start_date: &DateTime<Utc>,
-        _end_date: &DateTime<Utc>,
-    ) -> Result<Vec<MatchupOverview>, Box<dyn Error>> {
-        todo!();
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let started = Instant::now();
+        let client = self.adapter.get_connection().await?;
+        let result = client.select_by_date_range(start_date, end_date).await?;
+        debug!(
+            matchups = result.len(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "matchups selected"
+        );
+        Ok(result)
+    }
+
+    #[instrument(skip_all, fields(%start_date, %end_date))]
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        let started = Instant::now();
+        let client = self.adapter.get_connection().await?;
+        let (start_date, end_date) = (*start_date, *end_date);
+        // The stream of the adapter borrows the connection, so both go along.
+        Ok(Box::pin(try_stream! {
+            let mut matchups = client
+                .stream_by_date_range(&start_date, &end_date)
+                .await?;
+            let mut streamed = 0;
+            while let Some(matchup) = matchups.next().await {
+                yield matchup?;
+                streamed += 1;
+            }
+            debug!(
+                %start_date,
+                %end_date,
+                matchups = streamed,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "matchups streamed"
+            );
+        }))
+    }
+
+    #[instrument(skip_all, fields(?query))]
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let started = Instant::now();
+        let client = self.adapter.get_connection().await?;
+        let result = client.query(query).await?;
+        debug!(
+            matchups = result.len(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "matchups queried"
+        );
+        Ok(result)
+    }
+
+    async fn save_events<'life>(
+        &self,
+        events: &'life [MatchupEvent],
+    ) -> Result<(), PersistenceError> {
+        let client = self.adapter.get_connection().await?;
+        client.insert_events(events).await
+    }
+
+    async fn query_events(
+        &self,
+        query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+        let client = self.adapter.get_connection().await?;
+        client.select_events(query).await
+    }
+
+    async fn save_worlds<'life>(&self, worlds: &'life [World]) -> Result<(), PersistenceError> {
+        let client = self.adapter.get_connection().await?;
+        client.upsert_worlds(worlds).await
+    }
+
+    async fn query_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+        let client = self.adapter.get_connection().await?;
+        client.select_worlds().await
+    }
+
+    async fn save_objectives<'life>(
+        &self,
+        objectives: &'life [Objective],
+    ) -> Result<(), PersistenceError> {
+        let client = self.adapter.get_connection().await?;
+        client.upsert_objectives(objectives).await
+    }
+
+    async fn query_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+        let client = self.adapter.get_connection().await?;
+        client.select_objectives().await
+    }
+
+    async fn ping(&self) -> Result<(), PersistenceError> {
+        self.adapter.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, error::Error};
+
+    use db_adapter::postgres_adapter::PostgresOptions;
+
+    use crate::conformance;
+
+    use super::PostgresPersistence;
+
+    #[tokio::test]
+    #[ignore = "needs a reachable PostgreSQL, configured through POSTGRES_* variables, \
+               and writes to POSTGRES_CONFORMANCE_DATABASE"]
+    async fn passes_conformance_suite() -> Result<(), Box<dyn Error>> {
+        let host = env::var("POSTGRES_HOST")?;
+        let user = env::var("POSTGRES_USERNAME")?;
+        let password = env::var("POSTGRES_PASSWORD")?;
+        let mut options = PostgresOptions::with_credentials(&host, &user, &password);
+        options.config.dbname(
+            env::var("POSTGRES_CONFORMANCE_DATABASE")
+                .unwrap_or_else(|_| "gw2_wvw_conformance".to_string()),
+        );
+        let persistence = PostgresPersistence::with_options(&options)?;
+        persistence.create_tables().await?;
+        conformance::check_all(&persistence).await?;
+        conformance::check_events(&persistence).await?;
+        conformance::check_reference(&persistence).await
     }
 }
@@ -0,0 +1,790 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use db_adapter::{
+    db_adapter::MatchupStream,
+    error::PersistenceError,
+    query::{EventQuery, MatchupQuery},
+};
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{info, instrument, warn};
+
+use crate::persistence_system_interface::PersistenceSystem;
+
+const SPOOL_FILENAME: &str = "spool.ndjson";
+/// Spooled saves the backend refused for good, kept aside for a look.
+const DEAD_LETTER_FILENAME: &str = "dead-letter.ndjson";
+/// Spool lines that could not be read back, set aside as they were.
+const UNREADABLE_FILENAME: &str = "unreadable.ndjson";
+
+/// One save that could not reach the backend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpoolRecord {
+    pub spooled_at: DateTime<Utc>,
+    pub matchups: Vec<MatchupOverview>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<MatchupEvent>,
+}
+
+/// Append-only queue of failed saves, one JSON record per line.
+///
+/// Records are only removed once they were replayed, or moved to the dead
+/// letters, by rewriting the file with what is left and renaming it over the
+/// old one. Lines that cannot be read back are set aside before, so a rewrite
+/// loses nothing. Records are counted when the spool is opened and kept count
+/// of afterwards, so the backlog is known without reading the file.
+#[derive(Debug)]
+pub struct Spool {
+    path: PathBuf,
+    max_bytes: u64,
+    records: AtomicUsize,
+}
+
+impl Spool {
+    /// Opens, or creates, the spool kept in `dir`. Appends that would grow the
+    /// spool, with the dead letters and the lines set aside, past `max_bytes`
+    /// are refused.
+    pub fn open(dir: &Path, max_bytes: u64) -> Result<Self, std::io::Error> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(SPOOL_FILENAME);
+        if !path.exists() {
+            File::create(&path)?;
+        }
+        let mut spool = Self {
+            path,
+            max_bytes,
+            records: AtomicUsize::new(0),
+        };
+        let records = spool
+            .read_all()
+            .map_err(|err| std::io::Error::other(err.to_string()))?
+            .len();
+        *spool.records.get_mut() = records;
+        Ok(spool)
+    }
+
+    pub fn size_in_bytes(&self) -> Result<u64, std::io::Error> {
+        Ok(std::fs::metadata(&self.path)?.len())
+    }
+
+    /// Number of saves waiting to be replayed.
+    pub fn backlog(&self) -> usize {
+        self.records.load(Ordering::SeqCst)
+    }
+
+    pub fn append(&self, record: &SpoolRecord) -> Result<(), PersistenceError> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let size = self.size_in_bytes()?;
+        let used = size
+            + Self::file_size(&self.dead_letter_path())?
+            + Self::file_size(&self.unreadable_path())?;
+        if used + line.len() as u64 > self.max_bytes {
+            return Err(self.full(used, line.len()));
+        }
+
+        let mut fd = OpenOptions::new().append(true).open(&self.path)?;
+        if size > 0 && !self.ends_with_newline()? {
+            // Keeps a record cut short by a crash from swallowing this one.
+            fd.write_all(b"\n")?;
+        }
+        fd.write_all(&line)?;
+        fd.sync_data()?;
+        self.records.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Records in the order they were spooled. A line cut short by a crash is
+    /// skipped.
+    pub fn read_all(&self) -> Result<Vec<SpoolRecord>, PersistenceError> {
+        Self::read_records(&self.path)
+    }
+
+    /// Records the backend refused for good, in the order they were refused.
+    pub fn dead_letters(&self) -> Result<Vec<SpoolRecord>, PersistenceError> {
+        let path = self.dead_letter_path();
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        Self::read_records(&path)
+    }
+
+    /// Keeps `record` out of the replays, without losing it. The record moves
+    /// out of the spool, so only the dead letters on their own are held to
+    /// the cap.
+    fn dead_letter(&self, record: &SpoolRecord) -> Result<(), PersistenceError> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let path = self.dead_letter_path();
+        let used = Self::file_size(&path)?;
+        if used + line.len() as u64 > self.max_bytes {
+            return Err(self.full(used, line.len()));
+        }
+        Self::append_to(&path, &line)
+    }
+
+    fn dead_letter_path(&self) -> PathBuf {
+        self.path.with_file_name(DEAD_LETTER_FILENAME)
+    }
+
+    fn unreadable_path(&self) -> PathBuf {
+        self.path.with_file_name(UNREADABLE_FILENAME)
+    }
+
+    fn full(&self, used: u64, needed: usize) -> PersistenceError {
+        PersistenceError::Backend(format!(
+            "Spool {} is full: {} of {} bytes used, record needs {}",
+            self.path.display(),
+            used,
+            self.max_bytes,
+            needed
+        ))
+    }
+
+    fn file_size(path: &Path) -> Result<u64, std::io::Error> {
+        match std::fs::metadata(path) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn append_to(path: &Path, content: &[u8]) -> Result<(), PersistenceError> {
+        let mut fd = OpenOptions::new().create(true).append(true).open(path)?;
+        fd.write_all(content)?;
+        fd.sync_data()?;
+        Ok(())
+    }
+
+    fn read_records(path: &Path) -> Result<Vec<SpoolRecord>, PersistenceError> {
+        Ok(Self::read_lines(path)?.0)
+    }
+
+    /// Records of the file at `path`, and the lines that are not one.
+    fn read_lines(path: &Path) -> Result<(Vec<SpoolRecord>, Vec<String>), PersistenceError> {
+        let reader = BufReader::new(File::open(path)?);
+        let (mut records, mut unreadable) = (vec![], vec![]);
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(record) => records.push(record),
+                Err(err) => {
+                    warn!(error = %err, "skipping unreadable spool record");
+                    unreadable.push(line);
+                }
+            }
+        }
+        Ok((records, unreadable))
+    }
+
+    fn ends_with_newline(&self) -> Result<bool, std::io::Error> {
+        let mut fd = File::open(&self.path)?;
+        fd.seek(SeekFrom::End(-1))?;
+        let mut last = [0u8; 1];
+        fd.read_exact(&mut last)?;
+        Ok(last[0] == b'\n')
+    }
+
+    /// Replaces the spool content with `records`, after setting the
+    /// `unreadable` lines aside.
+    fn rewrite(
+        &self,
+        records: &[SpoolRecord],
+        unreadable: &[String],
+    ) -> Result<(), PersistenceError> {
+        if !unreadable.is_empty() {
+            let mut lines = unreadable.join("\n").into_bytes();
+            lines.push(b'\n');
+            Self::append_to(&self.unreadable_path(), &lines)?;
+            warn!(
+                lines = unreadable.len(),
+                path = %self.unreadable_path().display(),
+                "unreadable spool records set aside"
+            );
+        }
+        let tmp_path = self.path.with_extension("ndjson.tmp");
+        let mut fd = File::create(&tmp_path)?;
+        for record in records {
+            serde_json::to_writer(&mut fd, record)?;
+            fd.write_all(b"\n")?;
+        }
+        fd.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        self.records.store(records.len(), Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Wraps a persistence so saves that fail are kept in a local `Spool` instead
+/// of being lost, and replayed in order once the backend accepts saves again.
+/// Only transient failures are spooled: a save the backend refuses for good
+/// fails right away, and a spooled one it refuses goes to the dead letters.
+///
+/// While there is a backlog, new saves go to the end of the spool, so the
+/// backend always receives them in the order they were made. Saves and
+/// replays take turns, while the backlog can be read at any time.
+#[derive(Debug)]
+pub struct SpoolingPersistence<P> {
+    inner: P,
+    spool: Spool,
+    saving: Mutex<()>,
+}
+
+impl<P: PersistenceSystem + Send + Sync> SpoolingPersistence<P> {
+    pub fn new(inner: P, spool: Spool) -> Self {
+        Self {
+            inner,
+            spool,
+            saving: Mutex::new(()),
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Number of saves waiting to be replayed, without waiting for a save
+    /// going on.
+    pub fn backlog(&self) -> usize {
+        self.spool.backlog()
+    }
+
+    /// Saves the spooled records to the backend, oldest first, stopping at the
+    /// first one that fails for a transient reason. Records refused for good
+    /// are moved to the dead letters. Returns how many records were replayed.
+    pub async fn replay(&self) -> Result<usize, PersistenceError> {
+        let _saving = self.saving.lock().await;
+        Self::replay_locked(&self.inner, &self.spool).await
+    }
+
+    #[instrument(skip_all)]
+    async fn replay_locked(inner: &P, spool: &Spool) -> Result<usize, PersistenceError> {
+        let (records, unreadable) = Spool::read_lines(&spool.path)?;
+        let (mut replayed, mut dead) = (0, 0);
+        let mut failure = None;
+        for record in records.iter() {
+            match Self::save_record(inner, record).await {
+                Ok(()) => replayed += 1,
+                Err(err) if err.is_transient() => {
+                    failure = Some(err);
+                    break;
+                }
+                Err(err) => {
+                    warn!(error = %err, spooled_at = %record.spooled_at, "spooled save refused, moving it to the dead letters");
+                    if let Err(err) = spool.dead_letter(record) {
+                        failure = Some(err);
+                        break;
+                    }
+                    dead += 1;
+                }
+            }
+        }
+        let done = replayed + dead;
+        // Also sets unreadable lines aside, even when nothing was replayed.
+        if done > 0 || records.is_empty() || !unreadable.is_empty() {
+            spool.rewrite(&records[done..], &unreadable)?;
+        }
+        if done > 0 {
+            info!(
+                replayed,
+                dead,
+                left = records.len() - done,
+                "spooled saves replayed"
+            );
+        }
+        match failure {
+            Some(err) => Err(err.context(format!(
+                "Replayed {} of {} spooled saves",
+                replayed,
+                records.len()
+            ))),
+            None => Ok(replayed),
+        }
+    }
+
+    async fn save_record(inner: &P, record: &SpoolRecord) -> Result<(), PersistenceError> {
+        if !record.matchups.is_empty() {
+            inner.save(&record.matchups).await?;
+        }
+        if !record.events.is_empty() {
+            inner.save_events(&record.events).await?;
+        }
+        Ok(())
+    }
+
+    /// Saves the record, after the backlog, or appends it to the spool.
+    #[instrument(skip_all, fields(matchups = record.matchups.len(), events = record.events.len()))]
+    async fn save_or_spool(&self, record: SpoolRecord) -> Result<(), PersistenceError> {
+        let _saving = self.saving.lock().await;
+        let spool = &self.spool;
+
+        let has_backlog = spool.size_in_bytes()? > 0;
+        let backlog_failure = if has_backlog {
+            Self::replay_locked(&self.inner, spool).await.err()
+        } else {
+            None
+        };
+        let failure = match backlog_failure {
+            Some(failure) => failure,
+            None => match Self::save_record(&self.inner, &record).await {
+                Ok(()) => return Ok(()),
+                // Spooling it would only hold back the saves after it.
+                Err(err) if !err.is_transient() => return Err(err),
+                Err(err) => err,
+            },
+        };
+
+        warn!(error = %failure, "save failed, spooling it");
+        spool.append(&record)
+    }
+}
+
+#[async_trait]
+impl<P: PersistenceSystem + Send + Sync> PersistenceSystem for SpoolingPersistence<P> {
+    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), PersistenceError> {
+        self.save_or_spool(SpoolRecord {
+            spooled_at: Utc::now(),
+            matchups: obj.to_vec(),
+            events: vec![],
+        })
+        .await
+    }
+
+    async fn select_by_date_range(
+        &self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        self.inner.select_by_date_range(start_date, end_date).await
+    }
+
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        self.inner.stream_by_date_range(start_date, end_date).await
+    }
+
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        self.inner.query(query).await
+    }
+
+    /// Spooled like matchups, so events keep their order with the saves.
+    async fn save_events<'life>(
+        &self,
+        events: &'life [MatchupEvent],
+    ) -> Result<(), PersistenceError> {
+        self.save_or_spool(SpoolRecord {
+            spooled_at: Utc::now(),
+            matchups: vec![],
+            events: events.to_vec(),
+        })
+        .await
+    }
+
+    async fn query_events(
+        &self,
+        query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+        self.inner.query_events(query).await
+    }
+
+    /// Not spooled, the next run fetches the worlds again.
+    async fn save_worlds<'life>(&self, worlds: &'life [World]) -> Result<(), PersistenceError> {
+        self.inner.save_worlds(worlds).await
+    }
+
+    async fn query_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+        self.inner.query_worlds().await
+    }
+
+    /// Not spooled, the next run fetches the objectives again.
+    async fn save_objectives<'life>(
+        &self,
+        objectives: &'life [Objective],
+    ) -> Result<(), PersistenceError> {
+        self.inner.save_objectives(objectives).await
+    }
+
+    async fn query_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+        self.inner.query_objectives().await
+    }
+
+    async fn ping(&self) -> Result<(), PersistenceError> {
+        self.inner.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        error::Error,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use async_trait::async_trait;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use db_adapter::{
+        db_adapter::MatchupStream,
+        error::PersistenceError,
+        query::{EventQuery, MatchupQuery},
+    };
+    use gw2_api_models::models::{
+        matchup_event::{EventKind, MatchupEvent},
+        matchup_overview::{mock, MatchupOverview},
+        objective::Objective,
+        world::World,
+    };
+
+    use crate::{
+        in_memory_persistence::InMemoryPersistence, persistence_system_interface::PersistenceSystem,
+    };
+
+    use super::{Spool, SpoolRecord, SpoolingPersistence};
+
+    /// Records the order of the saves it accepts, and refuses them while down.
+    /// With `no_events`, refuses events for good, like a backend without a
+    /// place for them.
+    #[derive(Default)]
+    struct FlakyPersistence {
+        down: AtomicBool,
+        no_events: AtomicBool,
+        stored: InMemoryPersistence,
+        saved_ids: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl PersistenceSystem for FlakyPersistence {
+        async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), PersistenceError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(PersistenceError::Connection("backend is down".to_string()));
+            }
+            self.saved_ids
+                .lock()
+                .unwrap()
+                .extend(obj.iter().map(|matchup| matchup.id().clone()));
+            self.stored.save(obj).await
+        }
+
+        async fn select_by_date_range(
+            &self,
+            start_date: &DateTime<Utc>,
+            end_date: &DateTime<Utc>,
+        ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+            self.stored.select_by_date_range(start_date, end_date).await
+        }
+
+        async fn stream_by_date_range<'a>(
+            &'a self,
+            start_date: &DateTime<Utc>,
+            end_date: &DateTime<Utc>,
+        ) -> Result<MatchupStream<'a>, PersistenceError> {
+            self.stored.stream_by_date_range(start_date, end_date).await
+        }
+
+        async fn query(
+            &self,
+            query: &MatchupQuery,
+        ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+            self.stored.query(query).await
+        }
+
+        async fn save_events<'life>(
+            &self,
+            events: &'life [MatchupEvent],
+        ) -> Result<(), PersistenceError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(PersistenceError::Connection("backend is down".to_string()));
+            }
+            if self.no_events.load(Ordering::SeqCst) {
+                return Err(PersistenceError::Backend(
+                    "events are not stored".to_string(),
+                ));
+            }
+            self.stored.save_events(events).await
+        }
+
+        async fn query_events(
+            &self,
+            query: &EventQuery,
+        ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+            self.stored.query_events(query).await
+        }
+
+        async fn save_worlds<'life>(&self, worlds: &'life [World]) -> Result<(), PersistenceError> {
+            self.stored.save_worlds(worlds).await
+        }
+
+        async fn query_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+            self.stored.query_worlds().await
+        }
+
+        async fn save_objectives<'life>(
+            &self,
+            objectives: &'life [Objective],
+        ) -> Result<(), PersistenceError> {
+            self.stored.save_objectives(objectives).await
+        }
+
+        async fn query_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+            self.stored.query_objectives().await
+        }
+
+        async fn ping(&self) -> Result<(), PersistenceError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(PersistenceError::Connection("backend is down".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    fn matchup(id: &str) -> MatchupOverview {
+        let start = Utc.with_ymd_and_hms(2023, 5, 12, 18, 0, 0).unwrap();
+        mock::get_mock(id, start, start + Duration::days(7))
+    }
+
+    #[tokio::test]
+    async fn saves_directly_while_backend_is_up() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let persistence =
+            SpoolingPersistence::new(FlakyPersistence::default(), Spool::open(dir.path(), 4096)?);
+
+        persistence.save(&[matchup("1-1")]).await?;
+
+        assert_eq!(persistence.backlog(), 0);
+        assert_eq!(persistence.inner().stored.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn spools_while_down_and_replays_in_order() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let persistence = SpoolingPersistence::new(
+            FlakyPersistence::default(),
+            Spool::open(dir.path(), 1024 * 1024)?,
+        );
+
+        persistence.inner().down.store(true, Ordering::SeqCst);
+        persistence.save(&[matchup("1-1")]).await?;
+        persistence.save(&[matchup("1-2")]).await?;
+        assert_eq!(persistence.backlog(), 2);
+
+        persistence.inner().down.store(false, Ordering::SeqCst);
+        persistence.save(&[matchup("1-3")]).await?;
+
+        assert_eq!(persistence.backlog(), 0);
+        assert_eq!(
+            *persistence.inner().saved_ids.lock().unwrap(),
+            vec!["1-1", "1-2", "1-3"]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay_keeps_what_was_not_saved() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let persistence = SpoolingPersistence::new(
+            FlakyPersistence::default(),
+            Spool::open(dir.path(), 1024 * 1024)?,
+        );
+        persistence.inner().down.store(true, Ordering::SeqCst);
+        persistence.save(&[matchup("1-1")]).await?;
+
+        assert!(persistence.replay().await.is_err());
+        assert_eq!(persistence.backlog(), 1);
+
+        persistence.inner().down.store(false, Ordering::SeqCst);
+        assert_eq!(persistence.replay().await, Ok(1));
+        assert_eq!(persistence.backlog(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn spools_events_with_the_saves() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let persistence = SpoolingPersistence::new(
+            FlakyPersistence::default(),
+            Spool::open(dir.path(), 1024 * 1024)?,
+        );
+        let new_matchup = matchup("1-1");
+        let event = MatchupEvent {
+            match_id: "1-1".to_string(),
+            start_time: *new_matchup.start_time(),
+            detected_at: Utc::now(),
+            kind: EventKind::NewMatchup {
+                previous_start_time: None,
+            },
+        };
+
+        persistence.inner().down.store(true, Ordering::SeqCst);
+        persistence.save(&[new_matchup]).await?;
+        persistence
+            .save_events(std::slice::from_ref(&event))
+            .await?;
+        assert_eq!(persistence.backlog(), 2);
+
+        persistence.inner().down.store(false, Ordering::SeqCst);
+        assert_eq!(persistence.replay().await, Ok(2));
+        assert_eq!(
+            persistence.query_events(&EventQuery::new()).await?,
+            vec![event]
+        );
+        assert_eq!(persistence.inner().stored.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn does_not_spool_what_the_backend_refuses() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let persistence = SpoolingPersistence::new(
+            FlakyPersistence::default(),
+            Spool::open(dir.path(), 1024 * 1024)?,
+        );
+        persistence.inner().no_events.store(true, Ordering::SeqCst);
+        let event = MatchupEvent {
+            match_id: "1-1".to_string(),
+            start_time: *matchup("1-1").start_time(),
+            detected_at: Utc::now(),
+            kind: EventKind::NewMatchup {
+                previous_start_time: None,
+            },
+        };
+
+        persistence.inner().down.store(true, Ordering::SeqCst);
+        persistence.save(&[matchup("1-1")]).await?;
+        persistence
+            .save_events(std::slice::from_ref(&event))
+            .await?;
+        persistence.save(&[matchup("1-2")]).await?;
+        assert_eq!(persistence.backlog(), 3);
+
+        persistence.inner().down.store(false, Ordering::SeqCst);
+        persistence.save(&[matchup("1-3")]).await?;
+        assert!(persistence
+            .save_events(std::slice::from_ref(&event))
+            .await
+            .is_err());
+
+        assert_eq!(persistence.backlog(), 0);
+        assert_eq!(
+            *persistence.inner().saved_ids.lock().unwrap(),
+            vec!["1-1", "1-2", "1-3"]
+        );
+        let dead_letters = persistence.spool.dead_letters()?;
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].events, vec![event]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_saves_past_the_cap() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let persistence =
+            SpoolingPersistence::new(FlakyPersistence::default(), Spool::open(dir.path(), 100)?);
+        persistence.inner().down.store(true, Ordering::SeqCst);
+
+        let result = persistence.save(&[matchup("1-1")]).await;
+
+        assert!(result.is_err());
+        assert_eq!(persistence.backlog(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn backlog_survives_reopening() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let record = SpoolRecord {
+            spooled_at: Utc::now(),
+            matchups: vec![matchup("2-1")],
+            events: vec![],
+        };
+        Spool::open(dir.path(), 1024 * 1024)?.append(&record)?;
+
+        let reopened = Spool::open(dir.path(), 1024 * 1024)?;
+
+        assert_eq!(reopened.backlog(), 1);
+        assert_eq!(reopened.read_all()?, vec![record]);
+        Ok(())
+    }
+
+    #[test]
+    fn skips_a_record_cut_short() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let spool = Spool::open(dir.path(), 1024 * 1024)?;
+        let record = SpoolRecord {
+            spooled_at: Utc::now(),
+            matchups: vec![matchup("2-1")],
+            events: vec![],
+        };
+        spool.append(&record)?;
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("spool.ndjson"))
+            .and_then(|mut fd| std::io::Write::write_all(&mut fd, b"{\"spooled_at\":"))?;
+
+        assert_eq!(spool.read_all()?, vec![record.clone()]);
+        spool.append(&record)?;
+        assert_eq!(spool.read_all()?, vec![record.clone(), record]);
+        assert_eq!(spool.backlog(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sets_unreadable_records_aside_on_replay() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let persistence = SpoolingPersistence::new(
+            FlakyPersistence::default(),
+            Spool::open(dir.path(), 1024 * 1024)?,
+        );
+        persistence.inner().down.store(true, Ordering::SeqCst);
+        persistence.save(&[matchup("1-1")]).await?;
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("spool.ndjson"))
+            .and_then(|mut fd| std::io::Write::write_all(&mut fd, b"not a record\n"))?;
+
+        persistence.inner().down.store(false, Ordering::SeqCst);
+        assert_eq!(persistence.replay().await, Ok(1));
+
+        assert_eq!(std::fs::metadata(dir.path().join("spool.ndjson"))?.len(), 0);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("unreadable.ndjson"))?,
+            "not a record\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn counts_dead_letters_toward_the_cap() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let record = SpoolRecord {
+            spooled_at: Utc::now(),
+            matchups: vec![matchup("2-1")],
+            events: vec![],
+        };
+        let size = serde_json::to_vec(&record)?.len() as u64 + 1;
+        let spool = Spool::open(dir.path(), size * 2)?;
+        spool.dead_letter(&record)?;
+        spool.append(&record)?;
+
+        assert!(spool.append(&record).is_err());
+        assert!(spool.dead_letter(&record).is_ok());
+        assert!(spool.dead_letter(&record).is_err());
+        Ok(())
+    }
+}
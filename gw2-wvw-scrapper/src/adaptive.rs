@@ -0,0 +1,234 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use gw2_api_models::models::matchup_overview::MatchupOverview;
+use gw2_info_persistence::persistence_system_interface::PersistenceSystem;
+use serde::Deserialize;
+use tokio::sync::watch;
+
+use crate::{config::secs, job::ScrapeJob, scrape::Source};
+
+/// Skirmishes split each matchup in two hours windows, from its start time.
+pub const SKIRMISH: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// When a matchup runs, as the API last reported it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchupWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl From<&MatchupOverview> for MatchupWindow {
+    fn from(matchup: &MatchupOverview) -> Self {
+        Self {
+            start: *matchup.start_time(),
+            end: *matchup.end_time(),
+        }
+    }
+}
+
+impl MatchupWindow {
+    /// The skirmish or matchup boundaries around `now`: the latest one not
+    /// after it, and the next one after it, if the matchup isn't over.
+    fn boundaries(&self, now: DateTime<Utc>) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        if now < self.start {
+            return (None, Some(self.start));
+        }
+        if now >= self.end {
+            return (Some(self.end), None);
+        }
+        let skirmish = chrono::Duration::from_std(SKIRMISH).expect("two hours fit");
+        let elapsed = (now - self.start).num_seconds() / skirmish.num_seconds();
+        let previous = self.start + skirmish * elapsed as i32;
+        let next = (previous + skirmish).min(self.end);
+        (Some(previous), Some(next))
+    }
+}
+
+/// Scrapes every `dense_interval` within `dense_window` of a skirmish or
+/// matchup boundary, on either side, and every `sparse_interval` otherwise.
+/// A final snapshot is always taken `final_snapshot_lead` before each
+/// boundary.
+///
+/// ```toml
+/// [[scrapper.jobs]]
+/// name = "matches"
+/// adaptive = { dense_interval_secs = 30, sparse_interval_secs = 900 }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdaptiveConfig {
+    #[serde(rename = "dense_interval_secs", deserialize_with = "secs")]
+    pub dense_interval: Duration,
+    #[serde(rename = "sparse_interval_secs", deserialize_with = "secs")]
+    pub sparse_interval: Duration,
+    #[serde(rename = "dense_window_secs", deserialize_with = "secs")]
+    pub dense_window: Duration,
+    #[serde(rename = "final_snapshot_lead_secs", deserialize_with = "secs")]
+    pub final_snapshot_lead: Duration,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            dense_interval: Duration::from_secs(30),
+            sparse_interval: Duration::from_secs(10 * 60),
+            dense_window: Duration::from_secs(10 * 60),
+            final_snapshot_lead: Duration::from_secs(15),
+        }
+    }
+}
+
+impl AdaptiveConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.dense_interval.is_zero() || self.sparse_interval.is_zero() {
+            return Err("adaptive intervals must be above 0".to_string());
+        }
+        if self.dense_interval > self.sparse_interval {
+            return Err("dense_interval_secs must not exceed sparse_interval_secs".to_string());
+        }
+        if self.final_snapshot_lead >= SKIRMISH {
+            return Err("final_snapshot_lead_secs must be shorter than a skirmish".to_string());
+        }
+        Ok(())
+    }
+
+    /// When to scrape after `now`, given the matchups last seen. Without
+    /// any, the schedule stays sparse until a scrape succeeds.
+    pub fn next_run(&self, now: DateTime<Utc>, windows: &[MatchupWindow]) -> DateTime<Utc> {
+        let (previous, next) = windows.iter().map(|window| window.boundaries(now)).fold(
+            (None, None),
+            |(previous, next), (window_previous, window_next)| {
+                (previous.max(window_previous), min_some(next, window_next))
+            },
+        );
+        let within = |boundary: DateTime<Utc>| {
+            (boundary - now).abs().to_std().unwrap_or_default() <= self.dense_window
+        };
+        let interval = if previous.is_some_and(within) || next.is_some_and(within) {
+            self.dense_interval
+        } else {
+            self.sparse_interval
+        };
+        let mut next_run = now + chrono::Duration::from_std(interval).expect("interval fits");
+        if let Some(boundary) = next {
+            let window = chrono::Duration::from_std(self.dense_window).expect("window fits");
+            let lead = chrono::Duration::from_std(self.final_snapshot_lead).expect("lead fits");
+            // A sparse wait must not run past the start of the dense window,
+            // nor any wait past the final snapshot.
+            for stop in [boundary - window, boundary - lead] {
+                if stop > now && stop < next_run {
+                    next_run = stop;
+                }
+            }
+        }
+        next_run
+    }
+}
+
+/// Runs `job` right away, then whenever `config` says, until `stop` changes.
+/// A run going on then is finished first.
+pub async fn run<S, P>(
+    job: &ScrapeJob<S, P>,
+    config: AdaptiveConfig,
+    mut stop: watch::Receiver<bool>,
+) where
+    S: Source + Send + Sync,
+    P: PersistenceSystem + Send + Sync,
+{
+    loop {
+        job.run().await;
+        let now = Utc::now();
+        let wait = (config.next_run(now, &job.windows()) - now)
+            .to_std()
+            .unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = stop.changed() => return,
+        }
+    }
+}
+
+fn min_some(a: Option<DateTime<Utc>>, b: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    use super::{AdaptiveConfig, MatchupWindow};
+
+    fn window() -> MatchupWindow {
+        let start = Utc.with_ymd_and_hms(2023, 5, 5, 18, 0, 0).unwrap();
+        MatchupWindow {
+            start,
+            end: start + Duration::days(7),
+        }
+    }
+
+    fn at(hours: i64, minutes: i64, seconds: i64) -> DateTime<Utc> {
+        window().start
+            + Duration::hours(hours)
+            + Duration::minutes(minutes)
+            + Duration::seconds(seconds)
+    }
+
+    #[test]
+    fn is_sparse_within_a_skirmish() {
+        let config = AdaptiveConfig::default();
+
+        assert_eq!(config.next_run(at(1, 0, 0), &[window()]), at(1, 10, 0));
+    }
+
+    #[test]
+    fn is_dense_around_skirmish_ends() {
+        let config = AdaptiveConfig::default();
+
+        assert_eq!(config.next_run(at(1, 52, 0), &[window()]), at(1, 52, 30));
+        assert_eq!(config.next_run(at(2, 5, 0), &[window()]), at(2, 5, 30));
+        assert_eq!(config.next_run(at(2, 12, 0), &[window()]), at(2, 22, 0));
+    }
+
+    #[test]
+    fn starts_dense_scrapes_on_time_with_a_longer_sparse_interval() {
+        let config = AdaptiveConfig {
+            sparse_interval: std::time::Duration::from_secs(15 * 60),
+            ..AdaptiveConfig::default()
+        };
+
+        assert_eq!(config.next_run(at(1, 44, 0), &[window()]), at(1, 50, 0));
+        assert_eq!(config.next_run(at(1, 50, 0), &[window()]), at(1, 50, 30));
+    }
+
+    #[test]
+    fn takes_a_final_snapshot_before_each_boundary() {
+        let config = AdaptiveConfig::default();
+
+        assert_eq!(config.next_run(at(1, 59, 30), &[window()]), at(1, 59, 45));
+        assert_eq!(config.next_run(at(1, 59, 45), &[window()]), at(2, 0, 15));
+
+        let end = window().end;
+        assert_eq!(
+            config.next_run(end - Duration::seconds(20), &[window()]),
+            end - Duration::seconds(15)
+        );
+    }
+
+    #[test]
+    fn uses_the_nearest_boundary_of_every_matchup() {
+        let config = AdaptiveConfig::default();
+        let mut later = window();
+        later.start += Duration::hours(1);
+        later.end += Duration::hours(1);
+
+        assert_eq!(
+            config.next_run(at(2, 50, 0), &[window(), later]),
+            at(2, 50, 30)
+        );
+        assert_eq!(config.next_run(at(1, 0, 0), &[]), at(1, 10, 0));
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use db_adapter::error::PersistenceError;
+use gw2_api_models::models::matchup_overview::MatchupOverview;
+use gw2_info_persistence::{
+    file_format::FileFormat, persistence_system_interface::PersistenceSystem,
+};
+
+/// What a backfill imported, and the files it could not read.
+#[derive(Debug, Default)]
+pub struct BackfillReport {
+    pub files: usize,
+    pub matchups: usize,
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+/// Saves the matchups archived under `paths`, `batch_size` at a time.
+///
+/// Directories are walked recursively, keeping the files written by any
+/// `FileFormat`. Files are imported in path order, so archives named after
+/// their capture time are replayed chronologically. Files that can't be read
+/// are skipped and reported, while a failed save stops the backfill.
+pub async fn backfill<P>(
+    paths: &[PathBuf],
+    persistence: &P,
+    batch_size: usize,
+) -> Result<BackfillReport, PersistenceError>
+where
+    P: PersistenceSystem + ?Sized,
+{
+    let mut report = BackfillReport::default();
+    let mut batch = vec![];
+    for path in archive_files(paths)? {
+        let matchups = fs::read(&path)
+            .map_err(PersistenceError::from)
+            .and_then(|content| read_archive(&content));
+        match matchups {
+            Ok(matchups) => {
+                report.files += 1;
+                batch.extend(matchups);
+            }
+            Err(err) => report.skipped.push((path, err.to_string())),
+        }
+        if batch.len() >= batch_size {
+            persistence.save(&batch).await?;
+            report.matchups += batch.len();
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        persistence.save(&batch).await?;
+        report.matchups += batch.len();
+    }
+    Ok(report)
+}
+
+/// Reads either a saved API response, with every matchup of a capture, or a
+/// single matchup written in any `FileFormat`.
+pub fn read_archive(content: &[u8]) -> Result<Vec<MatchupOverview>, PersistenceError> {
+    if let Ok(matchups) = serde_json::from_slice::<Vec<MatchupOverview>>(content) {
+        return Ok(matchups);
+    }
+    Ok(vec![FileFormat::decode(content)?])
+}
+
+/// The archives under `paths`, in path order.
+pub fn archive_files(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            collect_dir(path, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn collect_dir(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_dir(&path, files)?;
+        } else if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(FileFormat::is_known_file)
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, fs};
+
+    use chrono::{Duration, TimeZone, Utc};
+    use gw2_api_models::models::matchup_overview::mock;
+    use gw2_info_persistence::{
+        file_format::FileFormat, in_memory_persistence::InMemoryPersistence,
+    };
+
+    use super::backfill;
+
+    #[tokio::test]
+    async fn imports_responses_and_single_matchups() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let start = Utc.with_ymd_and_hms(2023, 5, 5, 18, 0, 0).unwrap();
+        let end = start + Duration::days(7);
+        let response = vec![
+            mock::get_mock("1-1", start, end),
+            mock::get_mock("1-2", start, end),
+        ];
+        fs::create_dir(dir.path().join("2023"))?;
+        fs::write(
+            dir.path().join("2023/response.json"),
+            serde_json::to_vec(&response)?,
+        )?;
+        fs::write(
+            dir.path().join("2023/single.json.zst"),
+            FileFormat::JsonZstd.encode(&mock::get_mock("2-1", start, end))?,
+        )?;
+        fs::write(dir.path().join("broken.json"), b"{")?;
+        fs::write(dir.path().join("notes.txt"), b"not an archive")?;
+
+        let persistence = InMemoryPersistence::new();
+        let report = backfill(&[dir.path().to_path_buf()], &persistence, 2).await?;
+
+        assert_eq!(report.files, 2);
+        assert_eq!(report.matchups, 3);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].0.ends_with("broken.json"));
+        assert_eq!(persistence.len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,531 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use chrono::Utc;
+use gw2_info_persistence::config::{Backend, PersistenceConfig, CONFIG_PATH_VAR};
+use serde::{Deserialize, Deserializer};
+use tokio_cron_scheduler::Job;
+
+use crate::{adaptive::AdaptiveConfig, scrape::Endpoint};
+
+/// Everything the scrapper reads from the config file: the `[persistence]`
+/// table, shared with the info API, and its own `[scrapper]` table.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Config {
+    pub persistence: PersistenceConfig,
+    pub scrapper: ScrapperConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScrapperConfig {
+    /// Cron expression, with seconds, of the single job scraping every
+    /// matchup when no `jobs` are listed.
+    pub cron_schedule: String,
+    /// Where saves are kept while the backend is unreachable.
+    pub spool_dir: PathBuf,
+    pub spool_max_bytes: u64,
+    /// Longest wait for the running scrape, once asked to stop.
+    #[serde(rename = "shutdown_timeout_secs", deserialize_with = "secs")]
+    pub shutdown_timeout: Duration,
+    /// Where `/metrics`, `/healthz` and `/readyz` are served while running.
+    /// Nothing is served without it.
+    pub http_addr: Option<SocketAddr>,
+    /// Least age past which the latest scrape of a job fails the health
+    /// checks, see `JobConfig::max_scrape_age`.
+    #[serde(rename = "max_scrape_age_secs", deserialize_with = "secs")]
+    pub max_scrape_age: Duration,
+    pub jobs: Vec<JobConfig>,
+}
+
+/// One scrape on its own schedule, listed as a `[[scrapper.jobs]]` table:
+///
+/// ```toml
+/// [[scrapper.jobs]]
+/// name = "matches"
+/// cron_schedule = "*/30 * * * * *"
+///
+/// [[scrapper.jobs]]
+/// name = "worlds"
+/// endpoint = "worlds"
+/// cron_schedule = "0 0 4 * * *"
+///
+/// [[scrapper.jobs]]
+/// name = "guild-claims-eu"
+/// cron_schedule = "0 */5 * * * *"
+/// match_ids = ["2-1", "2-2", "2-3", "2-4", "2-5"]
+///
+/// [scrapper.jobs.persistence]
+/// backend = "file"
+/// file = { path = "claims" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobConfig {
+    /// Tells the job apart in logs, and names its spool directory when it
+    /// has its own persistence.
+    pub name: String,
+    /// Cron expression, with seconds. Either this or `adaptive` is set.
+    #[serde(default)]
+    pub cron_schedule: Option<String>,
+    /// Follows the skirmishes and matchups instead of a cron expression.
+    #[serde(default)]
+    pub adaptive: Option<AdaptiveConfig>,
+    /// What the job scrapes, the matches by default.
+    #[serde(default)]
+    pub endpoint: Endpoint,
+    /// Scrapes only these matches, instead of every current one.
+    #[serde(default)]
+    pub match_ids: Vec<String>,
+    /// Where the job saves, instead of the `[persistence]` table. The
+    /// environment does not override it.
+    #[serde(default)]
+    pub persistence: Option<PersistenceConfig>,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// Age past which the latest scrape of the job fails the health checks.
+    /// Without it, twice the longest wait of the schedule, and no less than
+    /// the `max_scrape_age_secs` of `[scrapper]`.
+    #[serde(default, rename = "max_scrape_age_secs", deserialize_with = "opt_secs")]
+    pub max_scrape_age: Option<Duration>,
+}
+
+impl JobConfig {
+    pub fn new(name: impl Into<String>, cron_schedule: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            cron_schedule: Some(cron_schedule.into()),
+            adaptive: None,
+            endpoint: Endpoint::default(),
+            match_ids: vec![],
+            persistence: None,
+            enabled: true,
+            max_scrape_age: None,
+        }
+    }
+
+    /// The `max_scrape_age` of the job, or the one of its schedule, not below
+    /// `least`.
+    pub fn max_scrape_age_or(&self, least: Duration) -> Duration {
+        if let Some(max_scrape_age) = self.max_scrape_age {
+            return max_scrape_age;
+        }
+        self.longest_wait()
+            .map_or(least, |wait| wait.saturating_mul(2).max(least))
+    }
+
+    /// Longest time between two runs, over the next week of a cron schedule.
+    fn longest_wait(&self) -> Option<Duration> {
+        if let Some(adaptive) = &self.adaptive {
+            return Some(adaptive.sparse_interval);
+        }
+        let schedule = cron::Schedule::from_str(self.cron_schedule.as_deref()?).ok()?;
+        // Every second for a week would be a lot of ticks for nothing.
+        let mut ticks = schedule.upcoming(Utc).take(10_000);
+        let first = ticks.next()?;
+        let (mut previous, mut longest) = (first, None);
+        for tick in ticks {
+            longest = longest.max((tick - previous).to_std().ok());
+            if tick - first > chrono::Duration::weeks(1) {
+                break;
+            }
+            previous = tick;
+        }
+        longest
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let is_valid_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if self.name.is_empty() || !self.name.chars().all(is_valid_char) {
+            return Err(format!(
+                "Invalid job name \"{}\": use letters, digits, '-' and '_'",
+                self.name
+            )
+            .into());
+        }
+        match (&self.cron_schedule, &self.adaptive) {
+            (Some(cron_schedule), None) => {
+                Job::new(cron_schedule.as_str(), |_, _| {}).map_err(|err| {
+                    format!(
+                        "Invalid cron_schedule \"{}\" for job {}: {}",
+                        cron_schedule, self.name, err
+                    )
+                })?;
+            }
+            (None, Some(adaptive)) => adaptive
+                .validate()
+                .map_err(|err| format!("Job {}: {}", self.name, err))?,
+            _ => {
+                return Err(format!(
+                    "Job {} needs either a cron_schedule or adaptive settings",
+                    self.name
+                )
+                .into())
+            }
+        }
+        if self.endpoint != Endpoint::Matches {
+            // Worlds and objectives have no matchups to follow or filter.
+            if self.adaptive.is_some() {
+                return Err(format!("Job {}: adaptive only follows the matches", self.name).into());
+            }
+            if !self.match_ids.is_empty() {
+                return Err(format!("Job {}: match_ids only filter the matches", self.name).into());
+            }
+        }
+        if let Some(persistence) = &self.persistence {
+            persistence
+                .validate()
+                .map_err(|err| format!("Job {}: {}", self.name, err))?;
+        }
+        Ok(())
+    }
+
+    /// Checks the job saves where its endpoint can be stored, `shared` being
+    /// the `[persistence]` table.
+    fn validate_backend(&self, shared: &PersistenceConfig) -> Result<(), Box<dyn Error>> {
+        let backend = self.persistence.as_ref().unwrap_or(shared).backend;
+        if self.endpoint != Endpoint::Matches && backend == Backend::Dynamo {
+            return Err(format!("Job {}: DynamoDB only stores matchups", self.name).into());
+        }
+        Ok(())
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Default for ScrapperConfig {
+    fn default() -> Self {
+        Self {
+            cron_schedule: String::from("0 1/1 * * * *"),
+            spool_dir: PathBuf::from("spool"),
+            spool_max_bytes: 512 * 1024 * 1024,
+            shutdown_timeout: Duration::from_secs(60),
+            http_addr: None,
+            max_scrape_age: Duration::from_secs(30 * 60),
+            jobs: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    scrapper: ScrapperConfig,
+}
+
+impl Config {
+    /// Reads `path`, or the file named by `GW2_CONFIG` when no path is given,
+    /// then applies the environment overrides. Without any file, everything
+    /// comes from the environment.
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os(CONFIG_PATH_VAR).map(PathBuf::from));
+        let config = match path {
+            Some(path) => {
+                let content = fs::read_to_string(&path)
+                    .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+                Self::from_toml(&content).map_err(|err| format!("{}: {}", path.display(), err))?
+            }
+            None => Self::default(),
+        };
+        config.with_env()
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, Box<dyn Error>> {
+        let file: ConfigFile = toml::from_str(content)?;
+        Ok(Self {
+            persistence: PersistenceConfig::from_toml(content)?,
+            scrapper: file.scrapper,
+        })
+    }
+
+    /// Overrides the values set in `CRON_SCHEDULE`, `SPOOL_DIR`,
+    /// `SPOOL_MAX_BYTES`, `SHUTDOWN_TIMEOUT_SECS`, `HTTP_ADDR` and
+    /// `MAX_SCRAPE_AGE_SECS`, on top of the persistence ones.
+    pub fn with_env(mut self) -> Result<Self, Box<dyn Error>> {
+        self.persistence = self.persistence.with_env()?;
+        if let Ok(cron_schedule) = env::var("CRON_SCHEDULE") {
+            self.scrapper.cron_schedule = cron_schedule;
+        }
+        if let Ok(spool_dir) = env::var("SPOOL_DIR") {
+            self.scrapper.spool_dir = PathBuf::from(spool_dir);
+        }
+        if let Ok(spool_max_bytes) = env::var("SPOOL_MAX_BYTES") {
+            self.scrapper.spool_max_bytes = spool_max_bytes
+                .parse()
+                .map_err(|err| format!("SPOOL_MAX_BYTES is invalid: {}", err))?;
+        }
+        if let Ok(secs) = env::var("SHUTDOWN_TIMEOUT_SECS") {
+            let secs = secs
+                .parse()
+                .map_err(|err| format!("SHUTDOWN_TIMEOUT_SECS is invalid: {}", err))?;
+            self.scrapper.shutdown_timeout = Duration::from_secs(secs);
+        }
+        if let Ok(http_addr) = env::var("HTTP_ADDR") {
+            let http_addr = http_addr
+                .parse()
+                .map_err(|err| format!("HTTP_ADDR is invalid: {}", err))?;
+            self.scrapper.http_addr = Some(http_addr);
+        }
+        if let Ok(secs) = env::var("MAX_SCRAPE_AGE_SECS") {
+            let secs = secs
+                .parse()
+                .map_err(|err| format!("MAX_SCRAPE_AGE_SECS is invalid: {}", err))?;
+            self.scrapper.max_scrape_age = Duration::from_secs(secs);
+        }
+        Ok(self)
+    }
+
+    /// The listed jobs, or a single one scraping every matchup on
+    /// `cron_schedule` when there are none.
+    pub fn jobs(&self) -> Vec<JobConfig> {
+        if self.scrapper.jobs.is_empty() {
+            vec![JobConfig::new(
+                "matches",
+                self.scrapper.cron_schedule.as_str(),
+            )]
+        } else {
+            self.scrapper.jobs.clone()
+        }
+    }
+
+    /// Checks every value, without connecting to anything.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        self.persistence.validate()?;
+        let jobs = self.jobs();
+        for (index, job) in jobs.iter().enumerate() {
+            job.validate()?;
+            job.validate_backend(&self.persistence)?;
+            if jobs[..index].iter().any(|other| other.name == job.name) {
+                return Err(format!("Job {} is listed twice", job.name).into());
+            }
+        }
+        if !jobs.iter().any(|job| job.enabled) {
+            return Err("Every job is disabled".into());
+        }
+        if self.scrapper.spool_max_bytes == 0 {
+            return Err("spool_max_bytes must be above 0".into());
+        }
+        Ok(())
+    }
+}
+
+pub fn secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    Ok(Duration::from_secs(u64::deserialize(deserializer)?))
+}
+
+fn opt_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use gw2_info_persistence::config::Backend;
+
+    use crate::{adaptive::AdaptiveConfig, scrape::Endpoint};
+
+    use super::{Config, JobConfig};
+
+    #[test]
+    fn reads_both_tables() {
+        let config = Config::from_toml(
+            r#"
+            [persistence]
+            backend = "file"
+
+            [persistence.file]
+            path = "archive"
+
+            [scrapper]
+            cron_schedule = "0 */5 * * * *"
+            shutdown_timeout_secs = 10
+            http_addr = "127.0.0.1:9184"
+            max_scrape_age_secs = 900
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.persistence.backend, Backend::File);
+        assert_eq!(config.scrapper.cron_schedule, "0 */5 * * * *");
+        assert_eq!(config.scrapper.shutdown_timeout, Duration::from_secs(10));
+        assert_eq!(
+            config.scrapper.http_addr,
+            Some("127.0.0.1:9184".parse().unwrap())
+        );
+        assert_eq!(config.scrapper.max_scrape_age, Duration::from_secs(900));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        let mut config = Config::from_toml("[persistence.file]\npath = \"archive\"").unwrap();
+        config.persistence.backend = Backend::File;
+        config.scrapper.cron_schedule = "every minute".to_string();
+
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("cron_schedule"), "{}", message);
+    }
+
+    #[test]
+    fn reads_jobs() {
+        let config = Config::from_toml(
+            r#"
+            [persistence]
+            backend = "file"
+
+            [persistence.file]
+            path = "archive"
+
+            [[scrapper.jobs]]
+            name = "matches"
+            cron_schedule = "*/30 * * * * *"
+
+            [[scrapper.jobs]]
+            name = "boundaries"
+            adaptive = { dense_interval_secs = 20 }
+            max_scrape_age_secs = 600
+
+            [[scrapper.jobs]]
+            name = "eu"
+            cron_schedule = "0 */5 * * * *"
+            match_ids = ["2-1", "2-2"]
+            enabled = false
+
+            [scrapper.jobs.persistence]
+            backend = "file"
+            file = { path = "eu" }
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let jobs = config.jobs();
+        assert_eq!(jobs.len(), 3);
+        assert_eq!(jobs[0], JobConfig::new("matches", "*/30 * * * * *"));
+        let adaptive = jobs[1].adaptive.unwrap();
+        assert_eq!(adaptive.dense_interval, Duration::from_secs(20));
+        assert_eq!(
+            adaptive.sparse_interval,
+            AdaptiveConfig::default().sparse_interval
+        );
+        assert_eq!(jobs[1].max_scrape_age, Some(Duration::from_secs(600)));
+        assert_eq!(jobs[2].match_ids, vec!["2-1", "2-2"]);
+        assert!(!jobs[2].enabled);
+        let persistence = jobs[2].persistence.as_ref().unwrap();
+        assert_eq!(persistence.backend, Backend::File);
+        assert_eq!(persistence.file.path, Some(PathBuf::from("eu")));
+    }
+
+    #[test]
+    fn falls_back_to_a_single_job() {
+        let config = Config::default();
+
+        assert_eq!(
+            config.jobs(),
+            vec![JobConfig::new(
+                "matches",
+                config.scrapper.cron_schedule.as_str()
+            )]
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_job_names() {
+        let mut config = Config::default();
+        config.persistence.backend = Backend::File;
+        config.persistence.file.path = Some(PathBuf::from("archive"));
+        config.scrapper.jobs = vec![
+            JobConfig::new("matches", "*/30 * * * * *"),
+            JobConfig::new("matches", "0 0 0 * * *"),
+        ];
+
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("listed twice"), "{}", message);
+    }
+
+    #[test]
+    fn derives_the_max_scrape_age_from_the_schedule() {
+        let least = Duration::from_secs(30 * 60);
+        let hourly = JobConfig::new("worlds", "0 0 * * * *");
+        let daily_at_nine = JobConfig::new("claims", "0 0 9 * * Mon-Fri");
+        let adaptive = JobConfig {
+            cron_schedule: None,
+            adaptive: Some(AdaptiveConfig {
+                sparse_interval: Duration::from_secs(60 * 60),
+                ..AdaptiveConfig::default()
+            }),
+            ..JobConfig::new("boundaries", "")
+        };
+        let fixed = JobConfig {
+            max_scrape_age: Some(Duration::from_secs(60)),
+            ..JobConfig::new("matches", "0 0 0 * * *")
+        };
+
+        assert_eq!(
+            JobConfig::new("matches", "*/30 * * * * *").max_scrape_age_or(least),
+            least
+        );
+        assert_eq!(
+            hourly.max_scrape_age_or(least),
+            Duration::from_secs(2 * 60 * 60)
+        );
+        assert_eq!(
+            daily_at_nine.max_scrape_age_or(least),
+            Duration::from_secs(2 * 3 * 24 * 60 * 60)
+        );
+        assert_eq!(
+            adaptive.max_scrape_age_or(least),
+            Duration::from_secs(2 * 60 * 60)
+        );
+        assert_eq!(fixed.max_scrape_age_or(least), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn checks_the_endpoint_of_jobs() {
+        let mut config = Config::from_toml(
+            r#"
+            [persistence]
+            backend = "file"
+
+            [persistence.file]
+            path = "archive"
+
+            [[scrapper.jobs]]
+            name = "worlds"
+            endpoint = "worlds"
+            cron_schedule = "0 0 4 * * *"
+
+            [[scrapper.jobs]]
+            name = "objectives"
+            endpoint = "objectives"
+            cron_schedule = "0 0 4 * * *"
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.jobs()[0].endpoint, Endpoint::Worlds);
+        assert_eq!(config.jobs()[1].endpoint, Endpoint::Objectives);
+
+        config.scrapper.jobs[1].match_ids = vec!["2-1".to_string()];
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("match_ids"), "{}", message);
+
+        config.scrapper.jobs[1].match_ids = vec![];
+        config.persistence.backend = Backend::Dynamo;
+        config.persistence.events = false;
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("DynamoDB"), "{}", message);
+    }
+}
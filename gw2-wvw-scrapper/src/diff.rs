@@ -0,0 +1,87 @@
+use db_adapter::error::PersistenceError;
+use gw2_api_models::models::matchup_overview::MatchupOverview;
+use serde_json::Value;
+
+/// Every value that differs between two matchups, one line each, as in
+/// `maps[0].scores.red: 10 -> 12`.
+pub fn diff(old: &MatchupOverview, new: &MatchupOverview) -> Result<Vec<String>, PersistenceError> {
+    let mut lines = vec![];
+    diff_values(
+        "",
+        &serde_json::to_value(old)?,
+        &serde_json::to_value(new)?,
+        &mut lines,
+    );
+    Ok(lines)
+}
+
+fn diff_values(path: &str, old: &Value, new: &Value, lines: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let path = join(path, key);
+                match new.get(key) {
+                    Some(new_value) => diff_values(&path, old_value, new_value, lines),
+                    None => lines.push(format!("{}: {} -> missing", path, old_value)),
+                }
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    lines.push(format!("{}: missing -> {}", join(path, key), new_value));
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            if old.len() != new.len() {
+                lines.push(format!(
+                    "{}: {} items -> {} items",
+                    path,
+                    old.len(),
+                    new.len()
+                ));
+            }
+            for (index, (old, new)) in old.iter().zip(new).enumerate() {
+                diff_values(&format!("{}[{}]", path, index), old, new, lines);
+            }
+        }
+        (old, new) if old != new => lines.push(format!("{}: {} -> {}", path, old, new)),
+        _ => {}
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use gw2_api_models::models::matchup_overview::mock;
+
+    use super::diff;
+
+    #[test]
+    fn lists_changed_values_by_path() {
+        let now = Utc::now();
+        let old = mock::get_mock("1-1", now, now);
+        let mut new = old.clone();
+        mock::set_scores(&mut new, 0, 5, 0);
+        mock::set_all_worlds(&mut new, vec![0], vec![0, 2], vec![3]);
+
+        assert!(diff(&old, &old).unwrap().is_empty());
+        let mut lines = diff(&old, &new).unwrap();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "all_worlds.blue: 1 items -> 2 items",
+                "all_worlds.green[0]: 0 -> 3",
+                "scores.blue: 0 -> 5",
+            ]
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use gw2_api_models::models::{
+    matchup_event::{EventKind, MatchupEvent},
+    matchup_overview::MatchupOverview,
+};
+
+/// The three teams of a matchup, each as its main world and its sorted
+/// linked worlds, main world included.
+fn teams(matchup: &MatchupOverview) -> [(u64, Vec<u64>); 3] {
+    let (main, all) = (matchup.worlds(), matchup.all_worlds());
+    let team = |world: u64, linked: &[u64]| {
+        let mut linked = linked.to_vec();
+        linked.sort_unstable();
+        (world, linked)
+    };
+    [
+        team(*main.red(), all.red()),
+        team(*main.blue(), all.blue()),
+        team(*main.green(), all.green()),
+    ]
+}
+
+/// Where each main world was last seen: its match, the start time of that
+/// matchup and its linked worlds. The latest matchup wins, stale snapshots
+/// of matches no longer scraped don't hide a move.
+fn placements(matchups: &[MatchupOverview]) -> HashMap<u64, (&str, DateTime<Utc>, Vec<u64>)> {
+    let mut placements: HashMap<u64, (&str, DateTime<Utc>, Vec<u64>)> = HashMap::new();
+    for matchup in matchups {
+        for (world, linked) in teams(matchup) {
+            let is_later = placements
+                .get(&world)
+                .is_none_or(|(_, start, _)| matchup.start_time() > start);
+            if is_later {
+                placements.insert(world, (matchup.id(), *matchup.start_time(), linked));
+            }
+        }
+    }
+    placements
+}
+
+/// The events between the latest stored matchup of each match, `previous`,
+/// and the fetched ones, `current`.
+///
+/// A match whose start time changed, or that was never stored, starts a new
+/// matchup. A team whose main world was last seen in another match changed
+/// tier, and one whose linked worlds differ from that last sighting was
+/// relinked.
+pub fn detect(
+    previous: &[MatchupOverview],
+    current: &[MatchupOverview],
+    detected_at: DateTime<Utc>,
+) -> Vec<MatchupEvent> {
+    let stored_starts: HashMap<&str, DateTime<Utc>> = previous
+        .iter()
+        .map(|matchup| (matchup.id().as_str(), *matchup.start_time()))
+        .collect();
+    let placements = placements(previous);
+
+    let mut events = vec![];
+    for matchup in current {
+        let event = |kind| MatchupEvent {
+            match_id: matchup.id().clone(),
+            start_time: *matchup.start_time(),
+            detected_at,
+            kind,
+        };
+        let previous_start_time = stored_starts.get(matchup.id().as_str()).copied();
+        if previous_start_time != Some(*matchup.start_time()) {
+            events.push(event(EventKind::NewMatchup {
+                previous_start_time,
+            }));
+        }
+        for (world, worlds) in teams(matchup) {
+            let Some((previous_match_id, _, previous_worlds)) = placements.get(&world) else {
+                continue;
+            };
+            if previous_match_id != matchup.id() {
+                events.push(event(EventKind::TierChange {
+                    world,
+                    previous_match_id: previous_match_id.to_string(),
+                }));
+            }
+            if *previous_worlds != worlds {
+                events.push(event(EventKind::Relink {
+                    world,
+                    previous_worlds: previous_worlds.clone(),
+                    worlds,
+                }));
+            }
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use gw2_api_models::models::{
+        matchup_event::EventKind,
+        matchup_overview::{mock, MatchupOverview},
+    };
+
+    use super::detect;
+
+    fn week(week: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 5, 5, 18, 0, 0).unwrap() + Duration::weeks(week)
+    }
+
+    fn matchup(id: &str, week_number: i64, worlds: [Vec<u64>; 3]) -> MatchupOverview {
+        let mut matchup = mock::get_mock(id, week(week_number), week(week_number + 1));
+        mock::set_worlds(&mut matchup, worlds[0][0], worlds[1][0], worlds[2][0]);
+        let [red, blue, green] = worlds;
+        mock::set_all_worlds(&mut matchup, red, blue, green);
+        matchup
+    }
+
+    fn kinds(previous: &[MatchupOverview], current: &[MatchupOverview]) -> Vec<EventKind> {
+        detect(previous, current, week(1))
+            .into_iter()
+            .map(|event| event.kind)
+            .collect()
+    }
+
+    #[test]
+    fn nothing_happens_within_a_matchup() {
+        let stored = vec![matchup("1-1", 0, [vec![1001], vec![1002], vec![1003]])];
+
+        assert_eq!(kinds(&stored, &stored), vec![]);
+    }
+
+    #[test]
+    fn tells_new_matchups() {
+        let stored = vec![matchup("1-1", 0, [vec![1001], vec![1002], vec![1003]])];
+        let next_week = vec![
+            matchup("1-1", 1, [vec![1001], vec![1002], vec![1003]]),
+            matchup("1-2", 1, [vec![1004], vec![1005], vec![1006]]),
+        ];
+
+        assert_eq!(
+            kinds(&stored, &next_week),
+            vec![
+                EventKind::NewMatchup {
+                    previous_start_time: Some(week(0)),
+                },
+                EventKind::NewMatchup {
+                    previous_start_time: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tells_tier_changes_and_relinks() {
+        let stored = vec![
+            matchup("1-1", 0, [vec![1001, 1010], vec![1002], vec![1003]]),
+            matchup("1-2", 0, [vec![1004], vec![1005], vec![1006]]),
+        ];
+        let next_week = vec![
+            matchup("1-1", 1, [vec![1001, 1011], vec![1002], vec![1004]]),
+            matchup("1-2", 1, [vec![1003], vec![1005], vec![1006]]),
+        ];
+
+        let events = detect(&stored, &next_week, week(1));
+        let changes: Vec<_> = events
+            .iter()
+            .filter(|event| !matches!(event.kind, EventKind::NewMatchup { .. }))
+            .map(|event| (event.match_id.as_str(), event.kind.clone()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    "1-1",
+                    EventKind::Relink {
+                        world: 1001,
+                        previous_worlds: vec![1001, 1010],
+                        worlds: vec![1001, 1011],
+                    }
+                ),
+                (
+                    "1-1",
+                    EventKind::TierChange {
+                        world: 1004,
+                        previous_match_id: "1-2".to_string(),
+                    }
+                ),
+                (
+                    "1-2",
+                    EventKind::TierChange {
+                        world: 1003,
+                        previous_match_id: "1-1".to_string(),
+                    }
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex as StdMutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use gw2_api_models::models::matchup_overview::MatchupOverview;
+use gw2_info_persistence::persistence_system_interface::PersistenceSystem;
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::{
+    adaptive::MatchupWindow,
+    metrics,
+    scrape::{self, Baseline, ScrapeError, Source},
+};
+
+/// Runs kept in the history, older ones are dropped.
+pub const HISTORY_LEN: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// Number of matchups, worlds or objectives saved.
+    Saved(usize),
+    Failed(ScrapeError),
+    /// The previous run was still going.
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunRecord {
+    pub started_at: DateTime<Utc>,
+    pub duration: Duration,
+    pub outcome: RunOutcome,
+}
+
+impl RunRecord {
+    pub fn finished_at(&self) -> DateTime<Utc> {
+        self.started_at + chrono::Duration::from_std(self.duration).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Default)]
+struct JobState {
+    consecutive_failures: u32,
+    history: VecDeque<RunRecord>,
+    windows: Vec<MatchupWindow>,
+    /// The matchups of the latest successful run, the baseline of the
+    /// events of the next one.
+    matchups: Option<Vec<MatchupOverview>>,
+    last_run: Option<DateTime<Utc>>,
+    last_success: Option<DateTime<Utc>>,
+}
+
+/// A scrape run on a schedule: one run at a time, each recorded.
+pub struct ScrapeJob<S, P> {
+    name: String,
+    source: S,
+    persistence: P,
+    events: bool,
+    running: Mutex<()>,
+    state: StdMutex<JobState>,
+}
+
+impl<S, P> ScrapeJob<S, P>
+where
+    S: Source + Send + Sync,
+    P: PersistenceSystem + Send + Sync,
+{
+    pub fn new(name: impl Into<String>, source: S, persistence: P) -> Self {
+        Self {
+            name: name.into(),
+            source,
+            persistence,
+            events: true,
+            running: Mutex::new(()),
+            state: StdMutex::new(JobState::default()),
+        }
+    }
+
+    /// Whether matchup events are detected, true by default. The first run
+    /// compares with the stored matchups, the next ones with the matchups of
+    /// the run before, so a job limited to some matches only sees the tier
+    /// changes between them.
+    pub fn with_events(mut self, events: bool) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn persistence(&self) -> &P {
+        &self.persistence
+    }
+
+    /// Scrapes once, unless a run is already going, and records the outcome.
+    pub async fn run(&self) -> RunOutcome {
+        let run = async {
+            let started_at = Utc::now();
+            let start = Instant::now();
+            let outcome = match self.running.try_lock() {
+                Ok(_running) => {
+                    let previous = self.state().matchups.clone();
+                    let baseline = match &previous {
+                        _ if !self.events => Baseline::Off,
+                        Some(previous) => Baseline::Known(previous),
+                        None => Baseline::Stored,
+                    };
+                    match scrape::scrape(&self.source, &self.persistence, baseline).await {
+                        Ok(scraped) => {
+                            let matchups = scraped.matchups();
+                            if !matchups.is_empty() {
+                                metrics::record_matchups(matchups);
+                                let mut state = self.state();
+                                state.windows = matchups.iter().map(MatchupWindow::from).collect();
+                                state.matchups = Some(matchups.to_vec());
+                            }
+                            RunOutcome::Saved(scraped.count())
+                        }
+                        Err(err) => RunOutcome::Failed(err),
+                    }
+                }
+                Err(_) => RunOutcome::Skipped,
+            };
+            self.record(RunRecord {
+                started_at,
+                duration: start.elapsed(),
+                outcome: outcome.clone(),
+            });
+            outcome
+        };
+        run.instrument(info_span!("scrape_job", job = %self.name))
+            .await
+    }
+
+    /// Waits for the running scrape, if any. No run starts while the guard
+    /// is held.
+    pub async fn idle(&self) -> MutexGuard<'_, ()> {
+        self.running.lock().await
+    }
+
+    /// Failed runs since the last successful one. Skipped runs don't count.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.state().consecutive_failures
+    }
+
+    /// Latest runs, oldest first.
+    pub fn history(&self) -> Vec<RunRecord> {
+        self.state().history.iter().cloned().collect()
+    }
+
+    /// When the latest run that wasn't skipped finished, successful or not.
+    pub fn last_run(&self) -> Option<DateTime<Utc>> {
+        self.state().last_run
+    }
+
+    /// When the latest successful run finished.
+    pub fn last_success(&self) -> Option<DateTime<Utc>> {
+        self.state().last_success
+    }
+
+    /// When the matchups of the latest successful run take place.
+    pub fn windows(&self) -> Vec<MatchupWindow> {
+        self.state().windows.clone()
+    }
+
+    fn record(&self, record: RunRecord) {
+        metrics::record_run(&self.name, &record);
+        let mut state = self.state();
+        let elapsed_ms = record.duration.as_millis() as u64;
+        match &record.outcome {
+            RunOutcome::Saved(saved) => {
+                state.consecutive_failures = 0;
+                state.last_run = Some(record.finished_at());
+                state.last_success = Some(record.finished_at());
+                info!(saved, elapsed_ms, "scrape saved");
+            }
+            RunOutcome::Failed(err) => {
+                state.consecutive_failures += 1;
+                state.last_run = Some(record.finished_at());
+                let failures = state.consecutive_failures;
+                // Transient errors are expected now and then, the others need
+                // someone to look.
+                if err.is_transient() {
+                    warn!(failures, elapsed_ms, error = %err, "scrape failed, transient error");
+                } else {
+                    error!(failures, elapsed_ms, error = %err, "scrape failed, permanent error");
+                }
+            }
+            RunOutcome::Skipped => warn!("previous scrape still running, skipping this one"),
+        }
+        if state.history.len() == HISTORY_LEN {
+            state.history.pop_front();
+        }
+        state.history.push_back(record);
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, JobState> {
+        // The state is always left consistent, even by a panicking holder.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use async_trait::async_trait;
+    use chrono::{Duration, TimeZone, Utc};
+    use db_adapter::query::EventQuery;
+    use gw2_api_models::models::matchup_overview::{mock, MatchupOverview};
+    use gw2_info_persistence::{
+        in_memory_persistence::InMemoryPersistence, persistence_system_interface::PersistenceSystem,
+    };
+    use tokio::sync::Notify;
+
+    use crate::scrape::{MatchupSource, ScrapeError};
+
+    use super::{RunOutcome, ScrapeJob};
+
+    /// Fails the calls listed in `failing`, counting from 0.
+    struct ScriptedSource {
+        calls: AtomicUsize,
+        failing: Vec<usize>,
+        release: Option<Arc<Notify>>,
+    }
+
+    #[async_trait]
+    impl MatchupSource for ScriptedSource {
+        async fn fetch(&self) -> Result<Vec<MatchupOverview>, ScrapeError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if let Some(release) = &self.release {
+                release.notified().await;
+            }
+            if self.failing.contains(&call) {
+                return Err(ScrapeError::Fetch {
+                    message: "503 Service Unavailable".to_string(),
+                    transient: true,
+                });
+            }
+            let start = Utc.with_ymd_and_hms(2023, 5, 5, 18, 0, 0).unwrap();
+            Ok(vec![mock::get_mock(
+                "1-1",
+                start,
+                start + Duration::days(7),
+            )])
+        }
+    }
+
+    fn source(failing: Vec<usize>) -> ScriptedSource {
+        ScriptedSource {
+            calls: AtomicUsize::new(0),
+            failing,
+            release: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_consecutive_failures() {
+        let job = ScrapeJob::new("test", source(vec![0, 1, 3]), InMemoryPersistence::new());
+
+        assert!(matches!(job.run().await, RunOutcome::Failed(err) if err.is_transient()));
+        job.run().await;
+        assert_eq!(job.consecutive_failures(), 2);
+
+        assert_eq!(job.run().await, RunOutcome::Saved(1));
+        assert_eq!(job.consecutive_failures(), 0);
+        job.run().await;
+        assert_eq!(job.consecutive_failures(), 1);
+        assert_eq!(job.history().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn skips_runs_while_one_is_going() {
+        let release = Arc::new(Notify::new());
+        let mut slow = source(vec![]);
+        slow.release = Some(release.clone());
+        let job = Arc::new(ScrapeJob::new("test", slow, InMemoryPersistence::new()));
+
+        let first = tokio::spawn({
+            let job = job.clone();
+            async move { job.run().await }
+        });
+        while job.source.calls.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(job.run().await, RunOutcome::Skipped);
+        release.notify_one();
+        assert_eq!(first.await.unwrap(), RunOutcome::Saved(1));
+
+        let outcomes: Vec<_> = job.history().into_iter().map(|run| run.outcome).collect();
+        assert_eq!(outcomes, vec![RunOutcome::Skipped, RunOutcome::Saved(1)]);
+        assert_eq!(job.consecutive_failures(), 0);
+    }
+
+    #[tokio::test]
+    async fn detects_events_against_the_previous_run() {
+        let job = ScrapeJob::new("test", source(vec![]), InMemoryPersistence::new());
+        job.run().await;
+        job.run().await;
+        let events = job.persistence().query_events(&EventQuery::new()).await;
+        assert_eq!(events.unwrap().len(), 1);
+
+        let job = ScrapeJob::new("test", source(vec![]), InMemoryPersistence::new());
+        let job = job.with_events(false);
+        job.run().await;
+        let events = job.persistence().query_events(&EventQuery::new()).await;
+        assert!(events.unwrap().is_empty());
+    }
+}
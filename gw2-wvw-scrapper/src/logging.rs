@@ -0,0 +1,28 @@
+use clap::ValueEnum;
+use tracing_subscriber::EnvFilter;
+
+/// Levels used when `RUST_LOG` is unset.
+const DEFAULT_FILTER: &str = "info";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogFormat {
+    /// One readable line per event, for terminals.
+    #[default]
+    Human,
+    /// One JSON object per event, span fields included, for log collectors.
+    Json,
+}
+
+/// Sends the logs to stderr in `format`. Levels come from `RUST_LOG`, as in
+/// `RUST_LOG=info,gw2_api_wrapper=debug`.
+pub fn init(format: LogFormat) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Human => builder.init(),
+        LogFormat::Json => builder.json().flatten_event(true).init(),
+    }
+}
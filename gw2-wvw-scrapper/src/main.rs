@@ -1,71 +1,479 @@
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use config::{Config, JobConfig};
 use gw2_api_wrapper::Gw2ApiWrapper;
 use gw2_info_persistence::{
-    mongo_persistence::MongoPersistence, persistence_system_interface::PersistenceSystem,
+    config::PersistenceConfig,
+    fan_out_persistence::SharedPersistence,
+    file_layout::FileLayout,
+    spool::{Spool, SpoolingPersistence},
 };
-use std::{env, error::Error};
-use tokio_cron_scheduler::{Job, JobScheduler};
+use job::{RunOutcome, ScrapeJob};
+use metrics::MeteredPersistence;
+use replay::ReplaySource;
+use scrape::{Change, EndpointSource, MatchupSource, ScrapeError};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
+use tokio::sync::watch;
+use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
+use tracing::{error, info, warn};
+
+mod adaptive;
+mod backfill;
+mod config;
+mod diff;
+mod events;
+mod job;
+mod logging;
+mod metrics;
+mod replay;
+mod scrape;
+mod server;
+mod shutdown;
+
+/// Saves the Guild Wars 2 WvW matchups into the configured persistence.
+///
+/// Settings come from the config file given with `--config`, or named by
+/// `GW2_CONFIG`, and from the environment. Log levels come from `RUST_LOG`.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// TOML config file.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[arg(long, global = true, env = "LOG_FORMAT", value_enum, default_value_t)]
+    log_format: logging::LogFormat,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run every enabled job on its schedule until stopped. The default.
+    Run,
+    /// Run every enabled job a single time, then exit.
+    Once,
+    /// Import matchups from archived files or directories.
+    Backfill {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Matchups saved at a time.
+        #[arg(long, default_value_t = 100)]
+        batch_size: usize,
+    },
+    /// Save archived snapshots in the order they were captured, with the
+    /// events they bring, as live scraping would have.
+    Replay {
+        /// Directory the file persistence wrote to.
+        dir: PathBuf,
+        /// Layout the archives were written with, to read their capture time
+        /// from. Files are dated by modification time without
+        /// `{captured_at}`.
+        #[arg(long, default_value_t)]
+        layout: FileLayout,
+        /// Replay this many times faster than captured, instead of as fast as
+        /// possible.
+        #[arg(long, value_parser = speed)]
+        speed: Option<f64>,
+    },
+    /// Check the config, without connecting to anything.
+    ValidateConfig,
+    /// Fetch the matchups and print how they differ from the stored ones,
+    /// without saving them.
+    DryRun,
+}
+
+fn speed(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        Ok(_) => Err("must be a positive number".to_string()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+// Usage errors exit with 2, as clap does.
+const EXIT_FAILED: u8 = 1;
+const EXIT_INVALID_CONFIG: u8 = 3;
+const EXIT_FETCH_FAILED: u8 = 4;
+const EXIT_PERSISTENCE_FAILED: u8 = 5;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> ExitCode {
     dotenv::dotenv().ok();
-    let scheduler = JobScheduler::new().await.unwrap();
-
-    // let default_basepath = String::from(".");
-    // let basepath = args.get(1).unwrap_or(&default_basepath).clone();
-    // let file_persistence = FileSystemPersistence::new(basepath);
-
-    let host: &str = &env::var("MONGO_HOST")
-        .expect("MONGO_HOST must be set.")
-        .to_owned();
-    let user: &str = &env::var("MONGO_USERNAME")
-        .expect("MONGO_USERNAME must be set.")
-        .to_owned();
-    let password: &str = &env::var("MONGO_PASSWORD")
-        .expect("MONGO_PASSWORD must be set.")
-        .to_owned();
-
-    let cron_schedule: &str = &env::var("CRON_SCHEDULE")
-        .unwrap_or(String::from("0 1/1 * * * *"))
-        .to_owned();
-
-    dbg!(&cron_schedule);
-
-    // let oracle_persistence = OraclePersistence::new(host, user, password);
-    let mongo_persistence = MongoPersistence::new(host, user, password).await;
-
-    let job = Job::new_async(cron_schedule, move |_, _| {
-        // let this_file_persistence = file_persistence.clone();
-        // let this_pg_persistence = pg_persistence.clone();
-        // let this_oracle_persistence = oracle_persistence.clone();
-
-        let this_mongo_persistence = mongo_persistence.clone();
-
-        Box::pin(async move {
-            dbg!("Running Job");
-            let api = Gw2ApiWrapper::create();
-            let ids = api.get_matchup_ids().await.unwrap();
-            dbg!(&ids);
-            let info = api.get_matchup_info(ids).await.unwrap();
-
-            // dbg!(&info);
-            // this_file_persistence.save(&info).await.unwrap();
-            // this_pg_persistence.save(&info).await.unwrap();
-            this_mongo_persistence.save(&info).await.unwrap();
-
-            dbg!("Saved");
-        })
+    let args = Args::parse();
+    logging::init(args.log_format);
+
+    let config = match Config::load(args.config.as_deref()).and_then(|config| {
+        config.validate()?;
+        Ok(config)
+    }) {
+        Ok(config) => config,
+        Err(err) => {
+            error!(error = %err, "invalid config");
+            return ExitCode::from(EXIT_INVALID_CONFIG);
+        }
+    };
+
+    match args.command.unwrap_or(Command::Run) {
+        Command::Run => run(&config).await,
+        Command::Once => once(&config).await,
+        Command::Backfill { paths, batch_size } => {
+            backfill(&config, &paths, batch_size.max(1)).await
+        }
+        Command::Replay { dir, layout, speed } => replay(&config, &dir, &layout, speed).await,
+        Command::ValidateConfig => {
+            println!(
+                "Config is valid, using the {:?} backend",
+                config.persistence.backend
+            );
+            ExitCode::SUCCESS
+        }
+        Command::DryRun => dry_run(&config).await,
+    }
+}
+
+async fn persistence(config: &PersistenceConfig) -> Result<SharedPersistence, ExitCode> {
+    config.build().await.map_err(|err| {
+        error!(error = %err, "could not open the persistence");
+        ExitCode::from(EXIT_PERSISTENCE_FAILED)
+    })
+}
+
+/// A configured job, saving through the spool of its persistence.
+type ConfiguredJob = ScrapeJob<EndpointSource, Arc<SpoolingPersistence<SharedPersistence>>>;
+
+/// `persistence`, metered, behind the spool in `spool_dir` keeping the saves
+/// it refuses.
+async fn spooling_persistence(
+    config: &PersistenceConfig,
+    spool_dir: &Path,
+    spool_max_bytes: u64,
+) -> Result<Arc<SpoolingPersistence<SharedPersistence>>, ExitCode> {
+    let persistence: SharedPersistence = Arc::new(MeteredPersistence::new(
+        self::persistence(config).await?,
+        config.backend.name(),
+    ));
+    let spool = Spool::open(spool_dir, spool_max_bytes).map_err(|err| {
+        error!(spool = %spool_dir.display(), error = %err, "could not open the spool");
+        ExitCode::from(EXIT_PERSISTENCE_FAILED)
+    })?;
+    Ok(Arc::new(SpoolingPersistence::new(persistence, spool)))
+}
+
+/// The enabled jobs. Those without their own persistence share the
+/// `[persistence]` one and the spool, the others get a spool directory named
+/// after them.
+async fn scrape_jobs(config: &Config) -> Result<Vec<(JobConfig, Arc<ConfiguredJob>)>, ExitCode> {
+    let settings = &config.scrapper;
+    let mut shared = None;
+    let mut jobs = vec![];
+    for job in config.jobs().into_iter().filter(|job| job.enabled) {
+        let events = job
+            .persistence
+            .as_ref()
+            .unwrap_or(&config.persistence)
+            .events;
+        let persistence = match &job.persistence {
+            Some(persistence) => {
+                let spool_dir = settings.spool_dir.join(&job.name);
+                spooling_persistence(persistence, &spool_dir, settings.spool_max_bytes).await?
+            }
+            None => match &shared {
+                Some(shared) => Arc::clone(shared),
+                None => {
+                    let persistence = spooling_persistence(
+                        &config.persistence,
+                        &settings.spool_dir,
+                        settings.spool_max_bytes,
+                    )
+                    .await?;
+                    shared.insert(persistence).clone()
+                }
+            },
+        };
+        let source = EndpointSource::new(job.endpoint, job.match_ids.clone());
+        let scrape_job = ScrapeJob::new(job.name.as_str(), source, persistence).with_events(events);
+        jobs.push((job, Arc::new(scrape_job)));
+    }
+    Ok(jobs)
+}
+
+fn exit_code(err: &ScrapeError) -> ExitCode {
+    match err {
+        ScrapeError::Fetch { .. } => ExitCode::from(EXIT_FETCH_FAILED),
+        ScrapeError::Persistence(_) => ExitCode::from(EXIT_PERSISTENCE_FAILED),
+    }
+}
+
+/// Runs every enabled job on its schedule, cron or adaptive, until SIGINT or
+/// SIGTERM. The scrapes running then, if any, get `shutdown_timeout_secs` to
+/// finish before the spools are replayed one last time.
+async fn run(config: &Config) -> ExitCode {
+    let scrape_jobs = match scrape_jobs(config).await {
+        Ok(jobs) => jobs,
+        Err(code) => return code,
+    };
+
+    let (stop, stopped) = watch::channel(false);
+    let mut http_server = None;
+    if let Some(addr) = config.scrapper.http_addr {
+        let state = Arc::new(server::ServerState {
+            jobs: scrape_jobs
+                .iter()
+                .map(|(settings, job)| server::WatchedJob {
+                    job: job.clone(),
+                    max_scrape_age: settings.max_scrape_age_or(config.scrapper.max_scrape_age),
+                })
+                .collect(),
+            started_at: Utc::now(),
+        });
+        match server::bind(addr, state, stopped.clone()) {
+            Ok(server) => http_server = Some(tokio::spawn(server)),
+            Err(err) => {
+                error!(%addr, error = %err, "could not serve HTTP");
+                return ExitCode::from(EXIT_FAILED);
+            }
+        }
+    }
+    let mut cron_jobs = vec![];
+    let mut adaptive_runs = vec![];
+    for (settings, scrape_job) in scrape_jobs.iter() {
+        let job_scrape = scrape_job.clone();
+        if let Some(adaptive) = settings.adaptive {
+            info!(
+                job = scrape_job.name(),
+                ?adaptive,
+                "scheduling adaptive job"
+            );
+            let stopped = stopped.clone();
+            adaptive_runs.push(tokio::spawn(async move {
+                adaptive::run(&job_scrape, adaptive, stopped).await
+            }));
+            continue;
+        }
+        let cron_schedule = settings.cron_schedule.as_deref().unwrap_or_default();
+        info!(
+            job = scrape_job.name(),
+            cron_schedule, "scheduling cron job"
+        );
+        cron_jobs.push(Job::new_async(cron_schedule, move |_, _| {
+            let this_scrape = job_scrape.clone();
+
+            Box::pin(async move {
+                this_scrape.run().await;
+                let backlog = this_scrape.persistence().backlog();
+                if backlog > 0 {
+                    warn!(
+                        job = this_scrape.name(),
+                        backlog, "saves waiting in the spool"
+                    );
+                }
+            })
+        }));
+    }
+
+    let mut scheduler = match start_scheduler(cron_jobs).await {
+        Ok(scheduler) => scheduler,
+        Err(err) => {
+            error!(error = %err, "could not start the scheduler");
+            return ExitCode::from(EXIT_FAILED);
+        }
+    };
+
+    match shutdown::signal().await {
+        Ok(signal) => info!(signal, "shutting down"),
+        Err(err) => error!(error = %err, "could not listen for signals, shutting down"),
+    }
+    if let Err(err) = scheduler.shutdown().await {
+        error!(error = %err, "could not stop the scheduler");
+    }
+    stop.send_replace(true);
+
+    if let Some(http_server) = http_server {
+        match http_server.await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => error!(error = %err, "the HTTP server failed"),
+            Err(err) => error!(error = %err, "the HTTP server stopped abruptly"),
+        }
+    }
+
+    let timeout = config.scrapper.shutdown_timeout;
+    let finished = tokio::time::timeout(timeout, async {
+        for adaptive_run in adaptive_runs {
+            if let Err(err) = adaptive_run.await {
+                error!(error = %err, "an adaptive job stopped abruptly");
+            }
+        }
+        let mut no_more_scrapes = vec![];
+        for (_, scrape_job) in scrape_jobs.iter() {
+            no_more_scrapes.push(scrape_job.idle().await);
+        }
+        // Jobs sharing a spool replay it once, the others find it empty.
+        for (_, scrape_job) in scrape_jobs.iter() {
+            if let Err(err) = scrape_job.persistence().replay().await {
+                warn!(job = scrape_job.name(), error = %err, "saves are left in the spool");
+            }
+        }
     })
-    .unwrap();
+    .await;
+    for (_, scrape_job) in scrape_jobs.iter() {
+        let history = scrape_job.history();
+        let count = |matches: fn(&RunOutcome) -> bool| {
+            history.iter().filter(|run| matches(&run.outcome)).count()
+        };
+        info!(
+            job = scrape_job.name(),
+            runs = history.len(),
+            failed = count(|outcome| matches!(outcome, RunOutcome::Failed(_))),
+            skipped = count(|outcome| matches!(outcome, RunOutcome::Skipped)),
+            failures_in_a_row = scrape_job.consecutive_failures(),
+            "job summary"
+        );
+    }
+    match finished {
+        Ok(()) => {
+            info!("shut down");
+            ExitCode::SUCCESS
+        }
+        Err(_) => {
+            error!(
+                timeout_secs = timeout.as_secs(),
+                "the last scrapes did not finish in time"
+            );
+            ExitCode::from(EXIT_FAILED)
+        }
+    }
+}
+
+async fn start_scheduler(
+    jobs: Vec<Result<Job, JobSchedulerError>>,
+) -> Result<JobScheduler, JobSchedulerError> {
+    let scheduler = JobScheduler::new().await?;
+    for job in jobs {
+        scheduler.add(job?).await?;
+    }
+    scheduler.start().await?;
+    Ok(scheduler)
+}
+
+/// Runs every enabled job a single time, one after the other. Exits with the
+/// code of the first failure.
+async fn once(config: &Config) -> ExitCode {
+    let jobs = match scrape_jobs(config).await {
+        Ok(jobs) => jobs,
+        Err(code) => return code,
+    };
+    let mut failure = None;
+    for (_, job) in jobs.iter() {
+        if let RunOutcome::Failed(err) = job.run().await {
+            failure.get_or_insert_with(|| exit_code(&err));
+        }
+        let backlog = job.persistence().backlog();
+        if backlog > 0 {
+            warn!(job = job.name(), backlog, "saves waiting in the spool");
+        }
+    }
+    failure.unwrap_or(ExitCode::SUCCESS)
+}
 
-    scheduler.add(job).await.unwrap();
+async fn backfill(config: &Config, paths: &[PathBuf], batch_size: usize) -> ExitCode {
+    let persistence = match persistence(&config.persistence).await {
+        Ok(persistence) => persistence,
+        Err(code) => return code,
+    };
+    let report = match backfill::backfill(paths, &*persistence, batch_size).await {
+        Ok(report) => report,
+        Err(err) => {
+            error!(error = %err, "backfill stopped");
+            return ExitCode::from(EXIT_PERSISTENCE_FAILED);
+        }
+    };
 
-    // TODO(Otavio): Wrap in tokio thread, aka: tokio::spawn
-    scheduler.start().await.unwrap();
+    println!(
+        "Imported {} matchups from {} files",
+        report.matchups, report.files
+    );
+    for (path, err) in report.skipped.iter() {
+        warn!(path = %path.display(), error = %err, "skipped");
+    }
+    if report.skipped.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_FAILED)
+    }
+}
+
+async fn replay(config: &Config, dir: &Path, layout: &FileLayout, speed: Option<f64>) -> ExitCode {
+    let persistence = match persistence(&config.persistence).await {
+        Ok(persistence) => persistence,
+        Err(code) => return code,
+    };
+    let source = match ReplaySource::open(dir, layout) {
+        Ok(source) => source,
+        Err(err) => {
+            error!(dir = %dir.display(), error = %err, "could not read the archives");
+            return ExitCode::from(EXIT_FAILED);
+        }
+    };
+    let report =
+        match replay::replay(&source, &*persistence, speed, config.persistence.events).await {
+            Ok(report) => report,
+            Err(err) => {
+                error!(error = %err, "replay stopped");
+                return exit_code(&err);
+            }
+        };
+
+    println!(
+        "Replayed {} matchups from {} snapshots",
+        report.matchups, report.snapshots
+    );
+    for (path, err) in report.skipped.iter() {
+        warn!(path = %path.display(), error = %err, "skipped");
+    }
+    if report.skipped.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(EXIT_FAILED)
+    }
+}
 
-    // Wait a while so that the jobs actually run
-    dbg!("All done, sleeping...");
-    tokio::time::sleep(core::time::Duration::from_secs(7 * 24 * 60 * 60)).await;
-    dbg!("Sleep done");
+async fn dry_run(config: &Config) -> ExitCode {
+    let persistence = match persistence(&config.persistence).await {
+        Ok(persistence) => persistence,
+        Err(code) => return code,
+    };
+    let api = Gw2ApiWrapper::create();
+    let matchups = match api.fetch().await {
+        Ok(matchups) => matchups,
+        Err(err) => {
+            error!(error = %err, "dry run stopped");
+            return exit_code(&err);
+        }
+    };
+    let changes = match scrape::changes(&matchups, &*persistence).await {
+        Ok(changes) => changes,
+        Err(err) => {
+            error!(error = %err, "could not read the stored matchups");
+            return ExitCode::from(EXIT_PERSISTENCE_FAILED);
+        }
+    };
 
-    Ok(())
+    for (matchup, change) in matchups.iter().zip(changes) {
+        let name = format!("{} ({})", matchup.id(), matchup.start_time().to_rfc3339());
+        match change {
+            Change::New => println!("{}: new", name),
+            Change::Unchanged => println!("{}: unchanged", name),
+            Change::Changed(lines) => {
+                println!("{}: {} changes", name, lines.len());
+                for line in lines {
+                    println!("  {}", line);
+                }
+            }
+        }
+    }
+    ExitCode::SUCCESS
 }
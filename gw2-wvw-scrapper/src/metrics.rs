@@ -0,0 +1,326 @@
+use std::{sync::LazyLock, time::Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use db_adapter::{
+    db_adapter::MatchupStream,
+    error::PersistenceError,
+    query::{EventQuery, MatchupQuery},
+};
+use gw2_api_models::models::{
+    matchup_event::MatchupEvent, matchup_overview::MatchupOverview, objective::Objective,
+    world::World,
+};
+use gw2_api_wrapper::ApiRequest;
+use gw2_info_persistence::persistence_system_interface::PersistenceSystem;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge_vec, Encoder,
+    HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+
+use crate::job::{RunOutcome, RunRecord};
+
+const TEAMS: [&str; 3] = ["red", "blue", "green"];
+
+static SCRAPE_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "gw2_scrapper_scrape_duration_seconds",
+        "Duration of the scrape runs, by job and outcome.",
+        &["job", "outcome"],
+        vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]
+    )
+    .expect("metric is registered once")
+});
+
+static LAST_SUCCESS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "gw2_scrapper_last_success_timestamp_seconds",
+        "Unix time of the latest successful scrape, by job.",
+        &["job"]
+    )
+    .expect("metric is registered once")
+});
+
+static API_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "gw2_scrapper_api_requests_total",
+        "Requests to the GW2 API, by HTTP status, \"none\" without a response.",
+        &["status"]
+    )
+    .expect("metric is registered once")
+});
+
+static PERSISTENCE_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "gw2_scrapper_persistence_duration_seconds",
+        "Duration of the persistence calls, by backend and operation.",
+        &["backend", "operation"],
+        vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0]
+    )
+    .expect("metric is registered once")
+});
+
+static PERSISTENCE_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "gw2_scrapper_persistence_errors_total",
+        "Failed persistence calls, by backend and operation.",
+        &["backend", "operation"]
+    )
+    .expect("metric is registered once")
+});
+
+static SPOOL_BACKLOG: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "gw2_scrapper_spool_backlog",
+        "Saves waiting in the spool of each job.",
+        &["job"]
+    )
+    .expect("metric is registered once")
+});
+
+static SCORE: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "gw2_matchup_score",
+        "Score of each team, as last scraped.",
+        &["match_id", "team"]
+    )
+    .expect("metric is registered once")
+});
+
+static PPT: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register_int_gauge_vec!(
+        "gw2_matchup_ppt",
+        "Points per tick of each team, from the objectives it holds.",
+        &["match_id", "team"]
+    )
+    .expect("metric is registered once")
+});
+
+/// Every metric, in the Prometheus text format.
+pub fn render() -> String {
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .expect("metrics encode to text");
+    String::from_utf8(buffer).expect("the text format is UTF-8")
+}
+
+pub fn record_api_request(request: &ApiRequest) {
+    let status = request
+        .status
+        .map(|status| status.to_string())
+        .unwrap_or_else(|| "none".to_string());
+    API_REQUESTS.with_label_values(&[&status]).inc();
+}
+
+pub fn record_run(job: &str, record: &RunRecord) {
+    let outcome = match record.outcome {
+        RunOutcome::Saved(_) => "saved",
+        RunOutcome::Failed(_) => "failed",
+        RunOutcome::Skipped => "skipped",
+    };
+    SCRAPE_DURATION
+        .with_label_values(&[job, outcome])
+        .observe(record.duration.as_secs_f64());
+    if let RunOutcome::Saved(_) = record.outcome {
+        LAST_SUCCESS
+            .with_label_values(&[job])
+            .set(record.finished_at().timestamp());
+    }
+}
+
+pub fn set_spool_backlog(job: &str, backlog: usize) {
+    SPOOL_BACKLOG.with_label_values(&[job]).set(backlog as i64);
+}
+
+/// Sets the score and PPT gauges of every matchup.
+pub fn record_matchups(matchups: &[MatchupOverview]) {
+    for matchup in matchups {
+        let scores = matchup.scores();
+        let scores = [*scores.red(), *scores.blue(), *scores.green()];
+        for ((team, score), ppt) in TEAMS.iter().zip(scores).zip(ppt(matchup)) {
+            let labels = [matchup.id().as_str(), team];
+            SCORE.with_label_values(&labels).set(score as i64);
+            PPT.with_label_values(&labels).set(ppt as i64);
+        }
+    }
+}
+
+/// Points each team, red, blue then green, earns per tick from the
+/// objectives it holds.
+pub fn ppt(matchup: &MatchupOverview) -> [u64; 3] {
+    let mut ppt = [0; 3];
+    let objectives = matchup.maps().iter().flat_map(|map| map.objectives());
+    for objective in objectives {
+        let team = TEAMS
+            .iter()
+            .position(|team| objective.owner().eq_ignore_ascii_case(team));
+        if let Some(team) = team {
+            ppt[team] += objective.points_tick();
+        }
+    }
+    ppt
+}
+
+/// Times every call to `inner` and counts the failed ones, labelled with
+/// `backend`. Streams are left out.
+pub struct MeteredPersistence<P> {
+    inner: P,
+    backend: &'static str,
+}
+
+impl<P> MeteredPersistence<P> {
+    pub fn new(inner: P, backend: &'static str) -> Self {
+        Self { inner, backend }
+    }
+
+    fn observe<T>(
+        &self,
+        operation: &str,
+        start: Instant,
+        result: Result<T, PersistenceError>,
+    ) -> Result<T, PersistenceError> {
+        let labels = [self.backend, operation];
+        PERSISTENCE_DURATION
+            .with_label_values(&labels)
+            .observe(start.elapsed().as_secs_f64());
+        if result.is_err() {
+            PERSISTENCE_ERRORS.with_label_values(&labels).inc();
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl<P: PersistenceSystem + Send + Sync> PersistenceSystem for MeteredPersistence<P> {
+    async fn save<'life>(&self, obj: &'life [MatchupOverview]) -> Result<(), PersistenceError> {
+        let start = Instant::now();
+        let result = self.inner.save(obj).await;
+        self.observe("save", start, result)
+    }
+
+    async fn select_by_date_range(
+        &self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let start = Instant::now();
+        let result = self.inner.select_by_date_range(start_date, end_date).await;
+        self.observe("select_by_date_range", start, result)
+    }
+
+    async fn stream_by_date_range<'a>(
+        &'a self,
+        start_date: &DateTime<Utc>,
+        end_date: &DateTime<Utc>,
+    ) -> Result<MatchupStream<'a>, PersistenceError> {
+        let start = Instant::now();
+        let result = self.inner.stream_by_date_range(start_date, end_date).await;
+        self.observe("stream_by_date_range", start, result)
+    }
+
+    async fn query(&self, query: &MatchupQuery) -> Result<Vec<MatchupOverview>, PersistenceError> {
+        let start = Instant::now();
+        let result = self.inner.query(query).await;
+        self.observe("query", start, result)
+    }
+
+    async fn save_events<'life>(
+        &self,
+        events: &'life [MatchupEvent],
+    ) -> Result<(), PersistenceError> {
+        let start = Instant::now();
+        let result = self.inner.save_events(events).await;
+        self.observe("save_events", start, result)
+    }
+
+    async fn query_events(
+        &self,
+        query: &EventQuery,
+    ) -> Result<Vec<MatchupEvent>, PersistenceError> {
+        let start = Instant::now();
+        let result = self.inner.query_events(query).await;
+        self.observe("query_events", start, result)
+    }
+
+    async fn save_worlds<'life>(&self, worlds: &'life [World]) -> Result<(), PersistenceError> {
+        let start = Instant::now();
+        let result = self.inner.save_worlds(worlds).await;
+        self.observe("save_worlds", start, result)
+    }
+
+    async fn query_worlds(&self) -> Result<Vec<World>, PersistenceError> {
+        let start = Instant::now();
+        let result = self.inner.query_worlds().await;
+        self.observe("query_worlds", start, result)
+    }
+
+    async fn save_objectives<'life>(
+        &self,
+        objectives: &'life [Objective],
+    ) -> Result<(), PersistenceError> {
+        let start = Instant::now();
+        let result = self.inner.save_objectives(objectives).await;
+        self.observe("save_objectives", start, result)
+    }
+
+    async fn query_objectives(&self) -> Result<Vec<Objective>, PersistenceError> {
+        let start = Instant::now();
+        let result = self.inner.query_objectives().await;
+        self.observe("query_objectives", start, result)
+    }
+
+    async fn ping(&self) -> Result<(), PersistenceError> {
+        let start = Instant::now();
+        let result = self.inner.ping().await;
+        self.observe("ping", start, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use chrono::{TimeZone, Utc};
+    use db_adapter::query::MatchupQuery;
+    use gw2_api_models::models::matchup_overview::mock;
+    use gw2_info_persistence::{
+        in_memory_persistence::InMemoryPersistence, persistence_system_interface::PersistenceSystem,
+    };
+
+    use super::{ppt, record_matchups, render, MeteredPersistence};
+
+    #[test]
+    fn sums_the_points_of_held_objectives() {
+        let start = Utc.with_ymd_and_hms(2023, 5, 5, 18, 0, 0).unwrap();
+        let mut matchup = mock::get_mock("2-1", start, start);
+        mock::add_objective(&mut matchup, "Red", 5);
+        mock::add_objective(&mut matchup, "Red", 10);
+        mock::add_objective(&mut matchup, "Green", 2);
+        mock::add_objective(&mut matchup, "Neutral", 5);
+
+        assert_eq!(ppt(&matchup), [15, 0, 2]);
+
+        mock::set_scores(&mut matchup, 120, 80, 95);
+        record_matchups(&[matchup]);
+        let rendered = render();
+        assert!(rendered.contains(r#"gw2_matchup_ppt{match_id="2-1",team="red"} 15"#));
+        assert!(rendered.contains(r#"gw2_matchup_score{match_id="2-1",team="green"} 95"#));
+    }
+
+    #[tokio::test]
+    async fn times_persistence_calls_by_backend() -> Result<(), Box<dyn Error>> {
+        let persistence = MeteredPersistence::new(InMemoryPersistence::new(), "metered-test");
+        persistence.save(&[mock::get_naive_mock()]).await?;
+        persistence.query(&MatchupQuery::new()).await?;
+
+        let rendered = render();
+        assert!(rendered.contains(
+            r#"gw2_scrapper_persistence_duration_seconds_count{backend="metered-test",operation="save"} 1"#
+        ));
+        assert!(rendered.contains(
+            r#"gw2_scrapper_persistence_duration_seconds_count{backend="metered-test",operation="query"} 1"#
+        ));
+        Ok(())
+    }
+}
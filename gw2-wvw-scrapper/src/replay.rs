@@ -0,0 +1,196 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use db_adapter::error::PersistenceError;
+use gw2_info_persistence::{
+    file_format::FileFormat,
+    file_layout::{FileLayout, Placeholder},
+    persistence_system_interface::PersistenceSystem,
+};
+use tracing::{debug, instrument};
+
+use crate::{
+    backfill::{archive_files, read_archive},
+    scrape::{self, Baseline, ScrapeError},
+};
+
+/// Archived snapshots, grouped by the time they were captured.
+pub struct ReplaySource {
+    captures: BTreeMap<DateTime<Utc>, Vec<PathBuf>>,
+}
+
+impl ReplaySource {
+    /// Finds the archives under `dir`. The capture time is read from the path
+    /// when `layout` has `{captured_at}`, and is the modification time of the
+    /// file, to the second, otherwise.
+    pub fn open(dir: &Path, layout: &FileLayout) -> io::Result<Self> {
+        let mut captures: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for path in archive_files(&[dir.to_path_buf()])? {
+            let captured_at = path
+                .strip_prefix(dir)
+                .ok()
+                .and_then(Path::to_str)
+                .and_then(FileFormat::strip_extension)
+                .and_then(|relative| layout.parse(Path::new(relative)))
+                .and_then(|values| values.timestamp(Placeholder::CapturedAt));
+            let captured_at = match captured_at {
+                Some(captured_at) => captured_at,
+                None => {
+                    let modified = DateTime::<Utc>::from(fs::metadata(&path)?.modified()?);
+                    Utc.timestamp_opt(modified.timestamp(), 0)
+                        .single()
+                        .unwrap_or(modified)
+                }
+            };
+            captures.entry(captured_at).or_default().push(path);
+        }
+        Ok(Self { captures })
+    }
+}
+
+/// What a replay saved, and the files it could not read.
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub snapshots: usize,
+    pub matchups: usize,
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+/// Saves the snapshots of `source` oldest first, with the events they bring
+/// unless `events` is false, as scrapes captured at their time would.
+///
+/// With a `speed`, waits between snapshots for the time between their
+/// captures divided by it, so `1.0` is real time. Without one, snapshots go
+/// as fast as the persistence takes them. Files that can't be read are
+/// skipped and reported, while a failed save stops the replay.
+#[instrument(skip_all)]
+pub async fn replay<P>(
+    source: &ReplaySource,
+    persistence: &P,
+    speed: Option<f64>,
+    events: bool,
+) -> Result<ReplayReport, ScrapeError>
+where
+    P: PersistenceSystem + ?Sized,
+{
+    // A snapshot may only hold some matches, so each one is compared with
+    // the stored matchups rather than the snapshot before.
+    let baseline = if events {
+        Baseline::Stored
+    } else {
+        Baseline::Off
+    };
+    let mut report = ReplayReport::default();
+    let mut previous: Option<DateTime<Utc>> = None;
+    for (captured_at, paths) in source.captures.iter() {
+        let mut matchups = vec![];
+        for path in paths {
+            let read = fs::read(path)
+                .map_err(PersistenceError::from)
+                .and_then(|content| read_archive(&content));
+            match read {
+                Ok(read) => matchups.extend(read),
+                Err(err) => report.skipped.push((path.clone(), err.to_string())),
+            }
+        }
+        if matchups.is_empty() {
+            continue;
+        }
+
+        if let (Some(speed), Some(previous)) = (speed, previous) {
+            let gap = (*captured_at - previous).to_std().unwrap_or_default();
+            tokio::time::sleep(gap.div_f64(speed)).await;
+        }
+        previous = Some(*captured_at);
+        scrape::save(&matchups, baseline, persistence, *captured_at).await?;
+        debug!(%captured_at, matchups = matchups.len(), "snapshot replayed");
+        report.snapshots += 1;
+        report.matchups += matchups.len();
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, fs};
+
+    use chrono::{Duration, TimeZone, Utc};
+    use db_adapter::query::{EventQuery, MatchupQuery};
+    use gw2_api_models::models::{
+        matchup_event::EventKind,
+        matchup_overview::{mock, MatchupOverview},
+    };
+    use gw2_info_persistence::{
+        file_format::FileFormat, file_layout::FileLayout,
+        in_memory_persistence::InMemoryPersistence,
+        persistence_system_interface::PersistenceSystem,
+    };
+
+    use super::{replay, ReplaySource};
+
+    #[tokio::test]
+    async fn replays_snapshots_in_capture_order() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let layout: FileLayout = "{match_id}/{captured_at}".parse()?;
+        let start = Utc.with_ymd_and_hms(2023, 5, 5, 18, 0, 0).unwrap();
+        let end = start + Duration::days(7);
+        let archive = |matchup: MatchupOverview, captured_at| -> Result<(), Box<dyn Error>> {
+            let path = dir
+                .path()
+                .join(layout.render(&matchup, &captured_at))
+                .with_extension("json");
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, FileFormat::CompactJson.encode(&matchup)?)?;
+            Ok(())
+        };
+        let mut first_tier = mock::get_mock("1-1", start, end);
+        mock::set_worlds(&mut first_tier, 1001, 1002, 1003);
+        let mut second_tier = mock::get_mock("1-2", start, end);
+        mock::set_worlds(&mut second_tier, 1004, 1005, 1006);
+        let mut next_week = mock::get_mock("1-1", end, end + Duration::days(7));
+        mock::set_worlds(&mut next_week, 1001, 1002, 1003);
+        // In path order, both captures of 1-1 come before the one of 1-2.
+        archive(first_tier, start)?;
+        archive(second_tier, start + Duration::hours(1))?;
+        archive(next_week.clone(), end)?;
+        fs::write(dir.path().join("1-2/broken.json"), b"{")?;
+
+        let source = ReplaySource::open(dir.path(), &layout)?;
+        let persistence = InMemoryPersistence::new();
+        let report = replay(&source, &persistence, None, true).await?;
+
+        assert_eq!(report.snapshots, 3);
+        assert_eq!(report.matchups, 3);
+        assert_eq!(report.skipped.len(), 1);
+        let stored = persistence
+            .query(&MatchupQuery::new().match_id("1-1").latest_per_match())
+            .await?;
+        assert_eq!(stored, vec![next_week]);
+        let events: Vec<_> = persistence
+            .query_events(&EventQuery::new())
+            .await?
+            .into_iter()
+            .map(|event| (event.match_id, event.detected_at, event.kind))
+            .collect();
+        let new_matchup = |previous_start_time| EventKind::NewMatchup {
+            previous_start_time,
+        };
+        assert_eq!(
+            events,
+            vec![
+                ("1-1".to_string(), start, new_matchup(None)),
+                (
+                    "1-2".to_string(),
+                    start + Duration::hours(1),
+                    new_matchup(None)
+                ),
+                ("1-1".to_string(), end, new_matchup(Some(start))),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,371 @@
+use std::{borrow::Cow, time::Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use db_adapter::{error::PersistenceError, query::MatchupQuery};
+use gw2_api_models::models::{
+    matchup_overview::MatchupOverview, objective::Objective, world::World,
+};
+use gw2_api_wrapper::Gw2ApiWrapper;
+use gw2_info_persistence::persistence_system_interface::PersistenceSystem;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::{debug, info, instrument, warn};
+
+use crate::{diff, events, metrics};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ScrapeError {
+    #[error("could not fetch from the API: {message}")]
+    Fetch { message: String, transient: bool },
+    #[error("could not persist the scrape: {0}")]
+    Persistence(#[from] PersistenceError),
+}
+
+impl ScrapeError {
+    /// Whether the next scheduled run may succeed without any change.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ScrapeError::Fetch { transient, .. } => *transient,
+            ScrapeError::Persistence(err) => err.is_transient(),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ScrapeError {
+    fn from(err: reqwest::Error) -> Self {
+        // A body that can't be decoded means the API changed, everything else
+        // is about reaching it.
+        let transient = match err.status() {
+            Some(status) => status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+            None => !err.is_decode() && !err.is_builder(),
+        };
+        ScrapeError::Fetch {
+            message: err.to_string(),
+            transient,
+        }
+    }
+}
+
+/// Where current matchups come from.
+#[async_trait]
+pub trait MatchupSource {
+    async fn fetch(&self) -> Result<Vec<MatchupOverview>, ScrapeError>;
+}
+
+#[async_trait]
+impl MatchupSource for Gw2ApiWrapper {
+    /// Every current matchup, as the API reports it.
+    async fn fetch(&self) -> Result<Vec<MatchupOverview>, ScrapeError> {
+        let ids = self.get_matchup_ids().await?;
+        Ok(self.get_matchup_info(ids).await?)
+    }
+}
+
+/// What a scrape brings, depending on the endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scraped {
+    Matchups(Vec<MatchupOverview>),
+    Worlds(Vec<World>),
+    Objectives(Vec<Objective>),
+}
+
+impl Scraped {
+    /// How many matchups, worlds or objectives.
+    pub fn count(&self) -> usize {
+        match self {
+            Scraped::Matchups(matchups) => matchups.len(),
+            Scraped::Worlds(worlds) => worlds.len(),
+            Scraped::Objectives(objectives) => objectives.len(),
+        }
+    }
+
+    /// The scraped matchups, none for the other endpoints.
+    pub fn matchups(&self) -> &[MatchupOverview] {
+        match self {
+            Scraped::Matchups(matchups) => matchups,
+            _ => &[],
+        }
+    }
+}
+
+/// Where a job gets what it saves.
+#[async_trait]
+pub trait Source {
+    async fn fetch(&self) -> Result<Scraped, ScrapeError>;
+}
+
+#[async_trait]
+impl<T: MatchupSource + Sync> Source for T {
+    async fn fetch(&self) -> Result<Scraped, ScrapeError> {
+        Ok(Scraped::Matchups(MatchupSource::fetch(self).await?))
+    }
+}
+
+/// The API endpoint a job scrapes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Endpoint {
+    /// `/v2/wvw/matches`: scores, maps and objectives with their guild
+    /// claims, for each matchup.
+    #[default]
+    Matches,
+    /// `/v2/worlds`: the name and population of every world.
+    Worlds,
+    /// `/v2/wvw/objectives`: the name, type, map and position of every
+    /// objective.
+    Objectives,
+}
+
+/// One endpoint of the API. Matches are limited to `match_ids` when it isn't
+/// empty.
+pub struct EndpointSource {
+    api: Gw2ApiWrapper,
+    endpoint: Endpoint,
+    match_ids: Vec<String>,
+}
+
+impl EndpointSource {
+    pub fn new(endpoint: Endpoint, match_ids: Vec<String>) -> Self {
+        Self {
+            api: Gw2ApiWrapper::create().on_request(metrics::record_api_request),
+            endpoint,
+            match_ids,
+        }
+    }
+}
+
+#[async_trait]
+impl Source for EndpointSource {
+    async fn fetch(&self) -> Result<Scraped, ScrapeError> {
+        Ok(match self.endpoint {
+            Endpoint::Matches if self.match_ids.is_empty() => {
+                Scraped::Matchups(MatchupSource::fetch(&self.api).await?)
+            }
+            Endpoint::Matches => {
+                Scraped::Matchups(self.api.get_matchup_info(self.match_ids.clone()).await?)
+            }
+            Endpoint::Worlds => Scraped::Worlds(self.api.get_worlds().await?),
+            Endpoint::Objectives => Scraped::Objectives(self.api.get_objectives().await?),
+        })
+    }
+}
+
+/// Fetches from `source` and saves what it brings, with the events of the
+/// matchups against `baseline`. Returns what was saved.
+pub async fn scrape<S, P>(
+    source: &S,
+    persistence: &P,
+    baseline: Baseline<'_>,
+) -> Result<Scraped, ScrapeError>
+where
+    S: Source + ?Sized,
+    P: PersistenceSystem + ?Sized,
+{
+    let scraped = source.fetch().await?;
+    match &scraped {
+        Scraped::Matchups(matchups) => save(matchups, baseline, persistence, Utc::now()).await?,
+        Scraped::Worlds(worlds) => persistence.save_worlds(worlds).await?,
+        Scraped::Objectives(objectives) => persistence.save_objectives(objectives).await?,
+    }
+    Ok(scraped)
+}
+
+/// What `save` compares the matchups with to detect events.
+#[derive(Debug, Clone, Copy)]
+pub enum Baseline<'a> {
+    /// The latest stored matchup of each match, read before saving.
+    Stored,
+    /// Matchups at hand, such as those of the previous scrape.
+    Known(&'a [MatchupOverview]),
+    /// No events are detected.
+    Off,
+}
+
+/// Saves matchups captured at `captured_at`, with the events they bring
+/// compared to `baseline`. Events are best effort: failing to detect or save
+/// them is only logged.
+#[instrument(skip_all, fields(matchups = matchups.len()))]
+pub async fn save<P>(
+    matchups: &[MatchupOverview],
+    baseline: Baseline<'_>,
+    persistence: &P,
+    captured_at: DateTime<Utc>,
+) -> Result<(), ScrapeError>
+where
+    P: PersistenceSystem + ?Sized,
+{
+    let previous = match baseline {
+        Baseline::Stored => {
+            // Matchups last a week, so two cover the previous one of every
+            // match.
+            let recent = MatchupQuery::new()
+                .overlapping(captured_at - Duration::weeks(2), captured_at)
+                .latest_per_match();
+            Some(persistence.query(&recent).await.map(Cow::Owned))
+        }
+        Baseline::Known(previous) => Some(Ok(Cow::Borrowed(previous))),
+        Baseline::Off => None,
+    };
+    let start = Instant::now();
+    persistence.save(matchups).await?;
+    debug!(
+        elapsed_ms = start.elapsed().as_millis() as u64,
+        "matchups saved"
+    );
+
+    let events = match previous {
+        Some(Ok(previous)) => events::detect(&previous, matchups, captured_at),
+        Some(Err(err)) => {
+            warn!(error = %err, "could not read the stored matchups, no events detected");
+            return Ok(());
+        }
+        None => return Ok(()),
+    };
+    if !events.is_empty() {
+        match persistence.save_events(&events).await {
+            Ok(()) => info!(events = events.len(), "matchup events saved"),
+            Err(err) => warn!(events = events.len(), error = %err, "could not save matchup events"),
+        }
+    }
+    Ok(())
+}
+
+/// How a fetched matchup differs from the stored one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// Nothing is stored for this match and start time yet.
+    New,
+    Unchanged,
+    /// One line per differing value, as in `scores.red: 10 -> 12`.
+    Changed(Vec<String>),
+}
+
+/// Compares each matchup with the latest one stored for its match.
+pub async fn changes<P>(
+    matchups: &[MatchupOverview],
+    persistence: &P,
+) -> Result<Vec<Change>, PersistenceError>
+where
+    P: PersistenceSystem + ?Sized,
+{
+    let mut changes = vec![];
+    for matchup in matchups {
+        let query = MatchupQuery::new()
+            .match_id(matchup.id())
+            .latest_per_match();
+        let stored = persistence.query(&query).await?;
+        let change = match stored.first() {
+            Some(stored) if stored.start_time() == matchup.start_time() => {
+                let lines = diff::diff(stored, matchup)?;
+                if lines.is_empty() {
+                    Change::Unchanged
+                } else {
+                    Change::Changed(lines)
+                }
+            }
+            _ => Change::New,
+        };
+        changes.push(change);
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use async_trait::async_trait;
+    use chrono::{Duration, TimeZone, Utc};
+    use db_adapter::query::EventQuery;
+    use gw2_api_models::models::{matchup_event::EventKind, matchup_overview::mock, world::World};
+    use gw2_info_persistence::{
+        in_memory_persistence::InMemoryPersistence, persistence_system_interface::PersistenceSystem,
+    };
+    use serde_json::json;
+
+    use super::{changes, save, scrape, Baseline, Change, ScrapeError, Scraped, Source};
+
+    struct WorldsSource(Vec<World>);
+
+    #[async_trait]
+    impl Source for WorldsSource {
+        async fn fetch(&self) -> Result<Scraped, ScrapeError> {
+            Ok(Scraped::Worlds(self.0.clone()))
+        }
+    }
+
+    #[tokio::test]
+    async fn tells_new_changed_and_unchanged_matchups() -> Result<(), Box<dyn Error>> {
+        let start = Utc.with_ymd_and_hms(2023, 5, 5, 18, 0, 0).unwrap();
+        let end = start + Duration::days(7);
+        let stored = vec![
+            mock::get_mock("1-1", start, end),
+            mock::get_mock("1-2", start, end),
+        ];
+        let persistence = InMemoryPersistence::new();
+        persistence.save(&stored).await?;
+
+        let mut changed = mock::get_mock("1-2", start, end);
+        mock::set_scores(&mut changed, 10, 0, 0);
+        let next_week = mock::get_mock("1-1", end, end + Duration::days(7));
+        let fetched = vec![stored[0].clone(), changed, next_week];
+
+        let changes = changes(&fetched, &persistence).await?;
+        assert_eq!(changes[0], Change::Unchanged);
+        assert_eq!(
+            changes[1],
+            Change::Changed(vec!["scores.red: 0 -> 10".to_string()])
+        );
+        assert_eq!(changes[2], Change::New);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn saves_the_events_of_a_new_matchup() -> Result<(), Box<dyn Error>> {
+        let start = Utc.with_ymd_and_hms(2023, 5, 5, 18, 0, 0).unwrap();
+        let end = start + Duration::days(7);
+        let persistence = InMemoryPersistence::new();
+        let matchups = [mock::get_mock("1-1", start, end)];
+        save(&matchups, Baseline::Stored, &persistence, start).await?;
+        save(&matchups, Baseline::Stored, &persistence, start).await?;
+        let next_week = mock::get_mock("1-1", end, end + Duration::days(7));
+        save(&[next_week], Baseline::Known(&matchups), &persistence, end).await?;
+
+        let kinds: Vec<_> = persistence
+            .query_events(&EventQuery::new())
+            .await?
+            .into_iter()
+            .map(|event| event.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                EventKind::NewMatchup {
+                    previous_start_time: None,
+                },
+                EventKind::NewMatchup {
+                    previous_start_time: Some(start),
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn saves_the_worlds_of_a_worlds_source() -> Result<(), Box<dyn Error>> {
+        let worlds: Vec<World> = serde_json::from_value(json!([
+            { "id": 2012, "name": "Piken Square", "population": "VeryHigh" },
+            { "id": 1001, "name": "Anvil Rock", "population": "Medium" },
+        ]))?;
+        let persistence = InMemoryPersistence::new();
+
+        let scraped = scrape(&WorldsSource(worlds.clone()), &persistence, Baseline::Off).await?;
+        assert_eq!(scraped.count(), 2);
+        assert!(scraped.matchups().is_empty());
+        let stored = persistence.query_worlds().await?;
+        assert_eq!(stored, vec![worlds[1].clone(), worlds[0].clone()]);
+        Ok(())
+    }
+}
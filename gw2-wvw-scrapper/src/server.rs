@@ -0,0 +1,267 @@
+use std::{
+    collections::BTreeMap, convert::Infallible, future::Future, net::SocketAddr, sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use gw2_info_persistence::health::{check_persistence, Check, HealthReport};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::{metrics, ConfiguredJob};
+
+/// What the endpoints report on.
+pub struct ServerState {
+    pub jobs: Vec<WatchedJob>,
+    /// Stands for the latest scrape of the jobs that did not run yet.
+    pub started_at: DateTime<Utc>,
+}
+
+/// A job, with the age past which its latest scrape fails the checks.
+pub struct WatchedJob {
+    pub job: Arc<ConfiguredJob>,
+    pub max_scrape_age: Duration,
+}
+
+/// Binds `addr` right away, so a taken port fails the start, and returns the
+/// server of `/metrics`, `/healthz` and `/readyz`, running until `stop`
+/// changes.
+pub fn bind(
+    addr: SocketAddr,
+    state: Arc<ServerState>,
+    mut stop: watch::Receiver<bool>,
+) -> Result<impl Future<Output = Result<(), hyper::Error>>, hyper::Error> {
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(request, &state).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    info!(%addr, "serving /metrics, /healthz and /readyz");
+    Ok(server.with_graceful_shutdown(async move {
+        let _ = stop.changed().await;
+    }))
+}
+
+async fn handle(request: Request<Body>, state: &ServerState) -> Response<Body> {
+    match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            // The backlog is read when asked for, without waiting for a save.
+            for WatchedJob { job, .. } in state.jobs.iter() {
+                metrics::set_spool_backlog(job.name(), job.persistence().backlog());
+            }
+            response(
+                StatusCode::OK,
+                "text/plain; version=0.0.4",
+                metrics::render(),
+            )
+        }
+        // Only a stalled scheduler is worth a restart: failing scrapes still
+        // run, and the backends do not come back with a restart.
+        (&Method::GET, "/healthz") => health(age_checks(state, Freshness::Run, Utc::now())),
+        (&Method::GET, "/readyz") => {
+            let mut checks = age_checks(state, Freshness::Success, Utc::now());
+            for WatchedJob { job, .. } in state.jobs.iter() {
+                checks.insert(
+                    format!("{}.persistence", job.name()),
+                    check_persistence(job.persistence()).await,
+                );
+            }
+            health(checks)
+        }
+        _ => response(StatusCode::NOT_FOUND, "text/plain", "Not Found".to_string()),
+    }
+}
+
+/// Which scrape an age check looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Freshness {
+    /// The latest one that ran, successful or not.
+    Run,
+    Success,
+}
+
+impl Freshness {
+    fn key(self) -> &'static str {
+        match self {
+            Freshness::Run => "last_run",
+            Freshness::Success => "last_success",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Freshness::Run => "scrape ran",
+            Freshness::Success => "successful scrape",
+        }
+    }
+}
+
+fn age_checks(
+    state: &ServerState,
+    freshness: Freshness,
+    now: DateTime<Utc>,
+) -> BTreeMap<String, Check> {
+    state
+        .jobs
+        .iter()
+        .map(
+            |WatchedJob {
+                 job,
+                 max_scrape_age,
+             }| {
+                let last = match freshness {
+                    Freshness::Run => job.last_run(),
+                    Freshness::Success => job.last_success(),
+                };
+                let check = age_check(freshness, last, state.started_at, now, *max_scrape_age);
+                (format!("{}.{}", job.name(), freshness.key()), check)
+            },
+        )
+        .collect()
+}
+
+/// Fails once the `last` scrape, or the start while there is none, is older
+/// than `max_age`.
+fn age_check(
+    freshness: Freshness,
+    last: Option<DateTime<Utc>>,
+    started_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+    max_age: Duration,
+) -> Check {
+    let age = (now - last.unwrap_or(started_at))
+        .to_std()
+        .unwrap_or_default();
+    let check = if age <= max_age {
+        Check::passed()
+    } else if last.is_some() {
+        Check::failed(format!(
+            "no {} for {} seconds",
+            freshness.describe(),
+            age.as_secs()
+        ))
+    } else {
+        Check::failed(format!(
+            "no {} since the start, {} seconds ago",
+            freshness.describe(),
+            age.as_secs()
+        ))
+    };
+    check
+        .with(
+            freshness.key(),
+            last.map(|at| at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        )
+        .with("age_secs", age.as_secs())
+        .with("max_age_secs", max_age.as_secs())
+}
+
+fn health(checks: BTreeMap<String, Check>) -> Response<Body> {
+    let report = HealthReport::new(checks);
+    let status = if report.ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = serde_json::to_string(&report).expect("reports serialize to JSON");
+    response(status, "application/json", body)
+}
+
+fn response(status: StatusCode, content_type: &str, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .expect("status and header are valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use super::{age_check, Freshness};
+
+    #[test]
+    fn fails_once_the_last_scrape_is_too_old() {
+        let started_at = Utc.with_ymd_and_hms(2023, 5, 5, 18, 0, 0).unwrap();
+        let max_age = Duration::from_secs(600);
+
+        let starting = age_check(Freshness::Success, None, started_at, started_at, max_age);
+        assert!(starting.ok);
+        let never = age_check(
+            Freshness::Success,
+            None,
+            started_at,
+            started_at + chrono::Duration::minutes(11),
+            max_age,
+        );
+        assert!(!never.ok);
+
+        let last_success = started_at + chrono::Duration::minutes(5);
+        let fresh = age_check(
+            Freshness::Success,
+            Some(last_success),
+            started_at,
+            last_success + chrono::Duration::minutes(10),
+            max_age,
+        );
+        assert!(fresh.ok);
+        let stale = age_check(
+            Freshness::Success,
+            Some(last_success),
+            started_at,
+            last_success + chrono::Duration::minutes(20),
+            max_age,
+        );
+        assert!(!stale.ok);
+        assert_eq!(
+            serde_json::to_value(&stale).unwrap(),
+            json!({
+                "ok": false,
+                "error": "no successful scrape for 1200 seconds",
+                "last_success": "2023-05-05T18:05:00Z",
+                "age_secs": 1200,
+                "max_age_secs": 600,
+            })
+        );
+    }
+
+    #[test]
+    fn tells_a_stalled_run_apart() {
+        let started_at = Utc.with_ymd_and_hms(2023, 5, 5, 18, 0, 0).unwrap();
+        let last_run = started_at + chrono::Duration::minutes(5);
+
+        let stalled = age_check(
+            Freshness::Run,
+            Some(last_run),
+            started_at,
+            last_run + chrono::Duration::minutes(20),
+            Duration::from_secs(600),
+        );
+
+        assert_eq!(
+            serde_json::to_value(&stalled).unwrap(),
+            json!({
+                "ok": false,
+                "error": "no scrape ran for 1200 seconds",
+                "last_run": "2023-05-05T18:05:00Z",
+                "age_secs": 1200,
+                "max_age_secs": 600,
+            })
+        );
+    }
+}
@@ -0,0 +1,21 @@
+use std::io;
+
+use tokio::signal;
+
+/// Resolves on the first SIGINT or SIGTERM, with the name of the signal.
+#[cfg(unix)]
+pub async fn signal() -> io::Result<&'static str> {
+    use tokio::signal::unix::{self, SignalKind};
+
+    let mut terminate = unix::signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = signal::ctrl_c() => result.map(|_| "SIGINT"),
+        _ = terminate.recv() => Ok("SIGTERM"),
+    }
+}
+
+/// Resolves on the first Ctrl-C, the only signal there is elsewhere.
+#[cfg(not(unix))]
+pub async fn signal() -> io::Result<&'static str> {
+    signal::ctrl_c().await.map(|_| "Ctrl-C")
+}